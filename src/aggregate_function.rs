@@ -0,0 +1,475 @@
+//! Rust aggregates as SQL functions, through DuckDB's aggregate function API
+//!
+//! Every registration keeps its aggregate in the crate's registry and gets a
+//! temporary macro of the registered name. The macro calls an aggregate
+//! function shared by all aggregates of the same signature, which takes the
+//! token of the connection and the registry key of the aggregate as its
+//! first arguments. The state DuckDB keeps for every group points to the
+//! [`AggregateFunction`]'s own state, made on the group's first row. Declare
+//! the function with `#[aggregate]` in diesel's `define_sql_function!` to use
+//! it in queries.
+
+use std::any::Any;
+use std::error::Error;
+use std::ffi::CString;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+use diesel::QueryResult;
+use duckdb::core::{FlatVector, LogicalTypeId};
+use duckdb::ffi::{
+    self, duckdb_aggregate_state, duckdb_data_chunk, duckdb_function_info, duckdb_vector, idx_t,
+};
+
+use crate::attach::quote_identifier;
+use crate::database::Database;
+use crate::error::MapQueryError;
+use crate::registry::{self, Registration};
+use crate::scalar_function::{
+    catch_panic, lookup_registration, signature_name, ScalarArgs, ScalarFunctionError, ScalarType,
+};
+use crate::virtual_table::write_value;
+use crate::DuckDbConnection;
+
+/// An aggregate that can be registered with
+/// [`DuckDbConnection::register_aggregate_function`]
+///
+/// ```ignore
+/// struct WeightedAverage;
+///
+/// impl AggregateFunction for WeightedAverage {
+///     type Args = (Double, Integer);
+///     type Ret = Nullable<Double>;
+///     type State = (f64, i64);
+///
+///     fn init(&self) -> (f64, i64) {
+///         (0.0, 0)
+///     }
+///
+///     fn update(&self, state: &mut (f64, i64), (value, weight): (f64, i32)) -> Result<(), ScalarFunctionError> {
+///         state.0 += value * f64::from(weight);
+///         state.1 += i64::from(weight);
+///         Ok(())
+///     }
+///
+///     fn combine(&self, state: &mut (f64, i64), other: (f64, i64)) -> Result<(), ScalarFunctionError> {
+///         state.0 += other.0;
+///         state.1 += other.1;
+///         Ok(())
+///     }
+///
+///     fn finalize(&self, (sum, weights): (f64, i64)) -> Result<Option<f64>, ScalarFunctionError> {
+///         Ok((weights != 0).then(|| sum / weights as f64))
+///     }
+/// }
+/// ```
+pub trait AggregateFunction: Send + Sync + 'static {
+    /// The tuple of the SQL types of the arguments, see [`ScalarArgs`]
+    type Args: ScalarArgs + 'static;
+    /// The SQL type of the result
+    type Ret: ScalarType + 'static;
+    /// The state a group's rows are folded into
+    ///
+    /// DuckDB folds the rows of a group on several threads and may merge a
+    /// state into more than one other state, e.g. for window functions, so
+    /// [`combine`](Self::combine) and [`finalize`](Self::finalize) get
+    /// clones of the states.
+    type State: Clone + Send + 'static;
+
+    /// The state of a group before its first row
+    fn init(&self) -> Self::State;
+
+    /// Fold the arguments of a row into `state`
+    ///
+    /// Not called for rows in which an argument whose type is not `Nullable`
+    /// is NULL.
+    fn update(
+        &self,
+        state: &mut Self::State,
+        args: <Self::Args as ScalarArgs>::Values,
+    ) -> Result<(), ScalarFunctionError>;
+
+    /// Merge the state of other rows of the same group into `state`
+    fn combine(
+        &self,
+        state: &mut Self::State,
+        other: Self::State,
+    ) -> Result<(), ScalarFunctionError>;
+
+    /// The result of a group
+    fn finalize(
+        &self,
+        state: Self::State,
+    ) -> Result<<Self::Ret as ScalarType>::Value, ScalarFunctionError>;
+}
+
+impl DuckDbConnection {
+    /// Make `function` callable as the SQL aggregate `name` on this connection
+    ///
+    /// The aggregate takes the arguments [`AggregateFunction::Args`], as in
+    /// the matching `define_sql_function!`, which needs the `#[aggregate]`
+    /// attribute to be usable with `group_by`:
+    ///
+    /// ```ignore
+    /// define_sql_function! {
+    ///     #[aggregate]
+    ///     fn weighted_average(value: Double, weight: Integer) -> Nullable<Double>;
+    /// }
+    ///
+    /// conn.register_aggregate_function("weighted_average", WeightedAverage)?;
+    /// let averages = orders::table
+    ///     .group_by(orders::user_id)
+    ///     .select((orders::user_id, weighted_average(orders::price, orders::quantity)))
+    ///     .load::<(i32, Option<f64>)>(&mut conn)?;
+    /// ```
+    ///
+    /// DuckDB calls [`AggregateFunction::init`] and
+    /// [`finalize`](AggregateFunction::finalize) for every group. Without
+    /// any rows and `GROUP BY`, the result is NULL, as for SQL's own
+    /// aggregates except `count`. Errors returned by `function`, and panics
+    /// in it, fail the query calling it.
+    ///
+    /// The aggregate is a temporary macro of this connection, registering
+    /// `name` again replaces it. The macro calls an aggregate function named
+    /// after the argument and result types, which is registered with the
+    /// database on first use and shared by all of its connections. Only
+    /// connections opened through [`DuckDbConnectionBuilder`] or
+    /// [`Connection::establish`], and their clones, can register aggregates,
+    /// as duckdb-rs doesn't expose the database of other connections.
+    ///
+    /// [`DuckDbConnectionBuilder`]: crate::DuckDbConnectionBuilder
+    /// [`Connection::establish`]: diesel::Connection::establish
+    pub fn register_aggregate_function<A: AggregateFunction>(
+        &mut self,
+        name: &str,
+        function: A,
+    ) -> QueryResult<()> {
+        let database = self.database.clone().ok_or_else(|| {
+            diesel::result::Error::QueryBuilderError(
+                "Aggregate functions can only be registered on connections opened through \
+                 DuckDbConnectionBuilder or Connection::establish"
+                    .into(),
+            )
+        })?;
+
+        let function = Arc::new(function);
+        let start: Start<A::Args, A::Ret> = Box::new(move || {
+            Box::new(Group {
+                state: function.init(),
+                function: Arc::clone(&function),
+            })
+        });
+        let registration = Registration::new(self.registry_owner()?, Arc::new(start));
+        let key = registration.key();
+
+        let parameters = A::Args::parameters();
+        let aggregate = signature_name(
+            "aggregate",
+            &parameters,
+            (A::Ret::column_type(), A::Ret::NULLABLE),
+        );
+        registry::register_once(self.as_ref(), &aggregate, || unsafe {
+            register::<A::Args, A::Ret>(&database, &aggregate)
+        })?;
+
+        let params = (0..parameters.len())
+            .map(|i| format!("arg{}", i))
+            .collect::<Vec<_>>();
+        let sql = format!(
+            "CREATE OR REPLACE TEMP MACRO {}({}) AS {}({}, {}, {})",
+            quote_identifier(name),
+            params.join(", "),
+            quote_identifier(&aggregate),
+            registry::OWNER_ARGUMENT,
+            key,
+            params.join(", ")
+        );
+        self.as_ref().execute_batch(&sql).map_query_error(&sql)?;
+
+        self.aggregate_functions
+            .insert(name.to_string(), registration);
+        Ok(())
+    }
+
+    /// Remove the aggregate `name` registered with
+    /// [`register_aggregate_function`](Self::register_aggregate_function)
+    ///
+    /// Does nothing if no such aggregate is registered.
+    pub fn unregister_aggregate_function(&mut self, name: &str) -> QueryResult<()> {
+        if self.aggregate_functions.remove(name).is_some() {
+            let sql = format!("DROP MACRO IF EXISTS temp.{}", quote_identifier(name));
+            self.as_ref().execute_batch(&sql).map_query_error(&sql)?;
+        }
+        Ok(())
+    }
+}
+
+// The state of a group with the aggregate folding it, whatever their types
+trait GroupState<Args: ScalarArgs, Ret: ScalarType>: Send {
+    fn update(&mut self, args: Args::Values) -> Result<(), ScalarFunctionError>;
+
+    fn combine(&mut self, other: &dyn GroupState<Args, Ret>) -> Result<(), ScalarFunctionError>;
+
+    fn finalize(&self) -> Result<Ret::Value, ScalarFunctionError>;
+
+    fn clone_group(&self) -> Box<dyn GroupState<Args, Ret>>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct Group<A: AggregateFunction> {
+    function: Arc<A>,
+    state: A::State,
+}
+
+impl<A: AggregateFunction> GroupState<A::Args, A::Ret> for Group<A> {
+    fn update(&mut self, args: <A::Args as ScalarArgs>::Values) -> Result<(), ScalarFunctionError> {
+        self.function.update(&mut self.state, args)
+    }
+
+    fn combine(
+        &mut self,
+        other: &dyn GroupState<A::Args, A::Ret>,
+    ) -> Result<(), ScalarFunctionError> {
+        let other = other
+            .as_any()
+            .downcast_ref::<Self>()
+            .ok_or("aggregate states of different functions combined")?;
+        self.function.combine(&mut self.state, other.state.clone())
+    }
+
+    fn finalize(&self) -> Result<<A::Ret as ScalarType>::Value, ScalarFunctionError> {
+        self.function.finalize(self.state.clone())
+    }
+
+    fn clone_group(&self) -> Box<dyn GroupState<A::Args, A::Ret>> {
+        Box::new(Self {
+            function: Arc::clone(&self.function),
+            state: self.state.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// A registered aggregate, making the state of a new group
+type Start<Args, Ret> = Box<dyn Fn() -> Box<dyn GroupState<Args, Ret>> + Send + Sync>;
+
+// The state DuckDB keeps for a group is a pointer to a boxed `GroupState`,
+// null until the group's first row. DuckDB doesn't align states, so the
+// pointer is read and written unaligned.
+type Slot<Args, Ret> = *mut Box<dyn GroupState<Args, Ret>>;
+
+unsafe fn group<'a, Args, Ret>(
+    state: duckdb_aggregate_state,
+) -> Option<&'a mut Box<dyn GroupState<Args, Ret>>>
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    state.cast::<Slot<Args, Ret>>().read_unaligned().as_mut()
+}
+
+unsafe fn set_group<Args, Ret>(state: duckdb_aggregate_state, group: Box<dyn GroupState<Args, Ret>>)
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    state
+        .cast::<Slot<Args, Ret>>()
+        .write_unaligned(Box::into_raw(Box::new(group)));
+}
+
+// Register the aggregate function `name` calling the aggregates taking
+// `Args` and returning `Ret` with `database`
+unsafe fn register<Args, Ret>(database: &Database, name: &str) -> duckdb::Result<()>
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    let c_name = CString::new(name)?;
+    let mut connection = ptr::null_mut();
+    let state = ffi::duckdb_connect(database.handle(), &mut connection);
+    if state != ffi::DuckDBSuccess {
+        ffi::duckdb_disconnect(&mut connection);
+        return Err(duckdb::Error::DuckDBFailure(
+            ffi::Error::new(state),
+            Some("connect error".to_string()),
+        ));
+    }
+
+    let mut function = ffi::duckdb_create_aggregate_function();
+    ffi::duckdb_aggregate_function_set_name(function, c_name.as_ptr());
+    let parameters = [LogicalTypeId::Varchar, LogicalTypeId::Bigint]
+        .into_iter()
+        .chain(
+            Args::parameters()
+                .into_iter()
+                .map(|(column_type, _)| column_type.type_id()),
+        );
+    for type_id in parameters {
+        let mut logical_type = ffi::duckdb_create_logical_type(type_id as ffi::duckdb_type);
+        ffi::duckdb_aggregate_function_add_parameter(function, logical_type);
+        ffi::duckdb_destroy_logical_type(&mut logical_type);
+    }
+    let mut logical_type =
+        ffi::duckdb_create_logical_type(Ret::column_type().type_id() as ffi::duckdb_type);
+    ffi::duckdb_aggregate_function_set_return_type(function, logical_type);
+    ffi::duckdb_destroy_logical_type(&mut logical_type);
+    ffi::duckdb_aggregate_function_set_functions(
+        function,
+        Some(state_size::<Args, Ret>),
+        Some(init::<Args, Ret>),
+        Some(update::<Args, Ret>),
+        Some(combine::<Args, Ret>),
+        Some(finalize::<Args, Ret>),
+    );
+    ffi::duckdb_aggregate_function_set_destructor(function, Some(destroy::<Args, Ret>));
+    // rows with NULL arguments are skipped by `update`, unless nullable
+    ffi::duckdb_aggregate_function_set_special_handling(function);
+
+    let state = ffi::duckdb_register_aggregate_function(connection, function);
+    ffi::duckdb_destroy_aggregate_function(&mut function);
+    ffi::duckdb_disconnect(&mut connection);
+    if state != ffi::DuckDBSuccess {
+        return Err(duckdb::Error::DuckDBFailure(
+            ffi::Error::new(state),
+            Some(format!("Cannot register the aggregate function {}", name)),
+        ));
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn state_size<Args, Ret>(_: duckdb_function_info) -> idx_t
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    std::mem::size_of::<Slot<Args, Ret>>() as idx_t
+}
+
+unsafe extern "C" fn init<Args, Ret>(_: duckdb_function_info, state: duckdb_aggregate_state)
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    state
+        .cast::<Slot<Args, Ret>>()
+        .write_unaligned(ptr::null_mut());
+}
+
+unsafe extern "C" fn update<Args, Ret>(
+    info: duckdb_function_info,
+    input: duckdb_data_chunk,
+    states: *mut duckdb_aggregate_state,
+) where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    let rows = ffi::duckdb_data_chunk_get_size(input) as usize;
+    if rows == 0 {
+        return;
+    }
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let vectors = (0..ffi::duckdb_data_chunk_get_column_count(input))
+            .map(|i| FlatVector::from(ffi::duckdb_data_chunk_get_vector(input, i)))
+            .collect::<Vec<_>>();
+        let start = lookup_registration::<Start<Args, Ret>>(&vectors[0], &vectors[1])?;
+        for (row, &state) in slice::from_raw_parts(states, rows).iter().enumerate() {
+            if group::<Args, Ret>(state).is_none() {
+                set_group(state, catch_panic("aggregate function", || Ok((*start)()))?);
+            }
+            let Some(args) = Args::read(&vectors[2..], row)? else {
+                continue;
+            };
+            let group = group::<Args, Ret>(state).expect("the group's state was just set");
+            catch_panic("aggregate function", || group.update(args))?;
+        }
+        Ok(())
+    })();
+    set_error(info, result);
+}
+
+unsafe extern "C" fn combine<Args, Ret>(
+    info: duckdb_function_info,
+    source: *mut duckdb_aggregate_state,
+    target: *mut duckdb_aggregate_state,
+    count: idx_t,
+) where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        for i in 0..count as usize {
+            let Some(source) = group::<Args, Ret>(*source.add(i)) else {
+                continue;
+            };
+            let target = *target.add(i);
+            match group::<Args, Ret>(target) {
+                Some(group) => catch_panic("aggregate function", || group.combine(&**source))?,
+                None => set_group(
+                    target,
+                    catch_panic("aggregate function", || Ok(source.clone_group()))?,
+                ),
+            }
+        }
+        Ok(())
+    })();
+    set_error(info, result);
+}
+
+unsafe extern "C" fn finalize<Args, Ret>(
+    info: duckdb_function_info,
+    source: *mut duckdb_aggregate_state,
+    result: duckdb_vector,
+    count: idx_t,
+    offset: idx_t,
+) where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    let mut output = FlatVector::from(result);
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        for i in 0..count as usize {
+            let row = offset as usize + i;
+            // no rows at all, without GROUP BY
+            let Some(group) = group::<Args, Ret>(*source.add(i)) else {
+                output.set_null(row);
+                continue;
+            };
+            let value = catch_panic("aggregate function", || group.finalize())?;
+            write_value(&mut output, row, Ret::column_type(), &Ret::to_value(value))?;
+        }
+        Ok(())
+    })();
+    set_error(info, result);
+}
+
+unsafe extern "C" fn destroy<Args, Ret>(states: *mut duckdb_aggregate_state, count: idx_t)
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    for &state in slice::from_raw_parts(states, count as usize) {
+        let slot = state.cast::<Slot<Args, Ret>>();
+        let group = slot.read_unaligned();
+        if !group.is_null() {
+            slot.write_unaligned(ptr::null_mut());
+            // panics must not unwind into DuckDB, and there is no query to fail
+            let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(group))));
+        }
+    }
+}
+
+// Fail the query calling the aggregate with the error of a callback
+unsafe fn set_error(info: duckdb_function_info, result: Result<(), Box<dyn Error>>) {
+    if let Err(e) = result {
+        let message =
+            CString::new(e.to_string().replace('\0', "")).expect("NUL characters were removed");
+        ffi::duckdb_aggregate_function_set_error(info, message.as_ptr());
+    }
+}
@@ -0,0 +1,259 @@
+//! Async access to DuckDB for tokio and other executors
+//!
+//! [`AsyncDuckDbConnection`] owns a [`DuckDbConnection`] on a dedicated
+//! thread and sends it work, so blocking DuckDB calls never run on the
+//! executor. It doesn't implement diesel-async's `AsyncConnection`: those
+//! traits expect the backend's rows to be produced by the async connection
+//! itself, while here every query runs to completion on the worker thread.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::task::{Context, Poll};
+
+use diesel::connection::{DefaultLoadingMode, SimpleConnection, TransactionManager};
+use diesel::query_dsl::methods::{ExecuteDsl, LoadQuery};
+use diesel::result::{ConnectionError, ConnectionResult, DatabaseErrorKind, Error as DieselError};
+use diesel::{Connection, QueryResult, RunQueryDsl};
+use tokio::sync::oneshot;
+
+use crate::{
+    DuckDbConnection, DuckDbConnectionBuilder, DuckDbErrorInformation, DuckDbTransactionManager,
+};
+
+/// Rows buffered by a [`DuckDbRowStream`] before the worker waits for the consumer
+const STREAM_BUFFER: usize = 64;
+
+type Job = Box<dyn FnOnce(&mut DuckDbConnection) + Send>;
+
+/// A DuckDB connection running on its own thread
+///
+/// Work is queued and runs in order, one job at a time. Closures passed to
+/// [`interact`](Self::interact) and [`transaction`](Self::transaction) run on
+/// the worker thread, so they are synchronous and should not block on the
+/// executor. The worker stops when the connection is dropped.
+pub struct AsyncDuckDbConnection {
+    jobs: mpsc::Sender<Job>,
+    // Whether the last job left a transaction open, updated by the worker
+    in_transaction: Arc<AtomicBool>,
+}
+
+impl AsyncDuckDbConnection {
+    /// Open the database at `database_url`, see [`Connection::establish`]
+    pub async fn establish(database_url: &str) -> ConnectionResult<Self> {
+        Self::from_builder(DuckDbConnectionBuilder::from_url(database_url)?).await
+    }
+
+    /// Open the database described by `builder`
+    pub async fn from_builder(builder: DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        let (opened, result) = oneshot::channel();
+        let connection = Self::spawn(move || {
+            let connection = builder.establish();
+            let status = connection.as_ref().map(|_| ()).map_err(|e| e.to_string());
+            let _ = opened.send(status);
+            connection.ok()
+        })?;
+
+        match result.await {
+            Ok(Ok(())) => Ok(connection),
+            Ok(Err(message)) => Err(ConnectionError::BadConnection(message)),
+            Err(_) => Err(ConnectionError::BadConnection(
+                "DuckDB worker thread stopped while connecting".to_string(),
+            )),
+        }
+    }
+
+    /// Move an open connection to a worker thread
+    pub fn from_connection(connection: DuckDbConnection) -> ConnectionResult<Self> {
+        Self::spawn(move || Some(connection))
+    }
+
+    /// Open another connection to the same database on its own worker thread,
+    /// see [`DuckDbConnection::try_clone`]
+    pub async fn try_clone(&self) -> ConnectionResult<Self> {
+        let connection = self
+            .run(|connection| connection.try_clone())
+            .await
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))??;
+        Self::from_connection(connection)
+    }
+
+    fn spawn(
+        open: impl FnOnce() -> Option<DuckDbConnection> + Send + 'static,
+    ) -> ConnectionResult<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+
+        std::thread::Builder::new()
+            .name("diesel-duckdb".to_string())
+            .spawn(move || {
+                if let Some(mut connection) = open() {
+                    for job in receiver {
+                        job(&mut connection);
+                    }
+                }
+            })
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+
+        Ok(Self {
+            jobs,
+            in_transaction: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Whether the last job finished inside a transaction, e.g. one begun in
+    /// [`interact`](Self::interact) or left open by a cancelled future
+    pub fn is_in_transaction(&self) -> bool {
+        self.in_transaction.load(Ordering::Relaxed)
+    }
+
+    // Queue `f`; panics are passed back to the caller instead of stopping the worker
+    fn run<R, F>(&self, f: F) -> impl Future<Output = QueryResult<R>>
+    where
+        F: FnOnce(&mut DuckDbConnection) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let in_transaction = Arc::clone(&self.in_transaction);
+        let queued = self
+            .jobs
+            .send(Box::new(move |connection: &mut DuckDbConnection| {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| f(connection)));
+                // Before sending the result, so the caller sees the new state
+                in_transaction.store(
+                    DuckDbTransactionManager::is_broken_transaction_manager(connection),
+                    Ordering::Relaxed,
+                );
+                let _ = sender.send(result);
+            }));
+
+        async move {
+            queued.map_err(|_| closed_connection())?;
+            match receiver.await {
+                Ok(Ok(value)) => Ok(value),
+                Ok(Err(panic)) => panic::resume_unwind(panic),
+                Err(_) => Err(closed_connection()),
+            }
+        }
+    }
+
+    /// Run `f` with the underlying connection on the worker thread
+    pub async fn interact<R, F>(&self, f: F) -> QueryResult<R>
+    where
+        F: FnOnce(&mut DuckDbConnection) -> QueryResult<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run(f).await?
+    }
+
+    pub async fn batch_execute(&self, query: impl Into<String>) -> QueryResult<()> {
+        let query = query.into();
+        self.interact(move |connection| connection.batch_execute(&query))
+            .await
+    }
+
+    /// Execute an insert, update or delete and return the number of affected rows
+    pub async fn execute<T>(&self, query: T) -> QueryResult<usize>
+    where
+        T: RunQueryDsl<DuckDbConnection> + ExecuteDsl<DuckDbConnection> + Send + 'static,
+    {
+        self.interact(move |connection| query.execute(connection))
+            .await
+    }
+
+    /// Load all rows of `query`
+    pub async fn load<T, U>(&self, query: T) -> QueryResult<Vec<U>>
+    where
+        T: RunQueryDsl<DuckDbConnection> + LoadQuery<'static, DuckDbConnection, U> + Send + 'static,
+        U: Send + 'static,
+    {
+        self.interact(move |connection| query.load(connection))
+            .await
+    }
+
+    /// Load the single row of `query`
+    pub async fn get_result<T, U>(&self, query: T) -> QueryResult<U>
+    where
+        T: RunQueryDsl<DuckDbConnection> + LoadQuery<'static, DuckDbConnection, U> + Send + 'static,
+        U: Send + 'static,
+    {
+        self.interact(move |connection| query.get_result(connection))
+            .await
+    }
+
+    /// Load all rows of `query` and hand them out one at a time as a stream
+    ///
+    /// The whole result is read into memory on the worker thread before the
+    /// first row is sent; rows are then converted as the stream is polled.
+    /// The worker is busy until the stream is consumed or dropped.
+    pub fn load_buffered_stream<T, U>(&self, query: T) -> DuckDbRowStream<U>
+    where
+        T: RunQueryDsl<DuckDbConnection> + LoadQuery<'static, DuckDbConnection, U> + Send + 'static,
+        U: Send + 'static,
+    {
+        let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+        let closed = sender.clone();
+
+        let job = move |connection: &mut DuckDbConnection| {
+            let rows = match query.load_iter::<U, DefaultLoadingMode>(connection) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    let _ = sender.blocking_send(Err(e));
+                    return;
+                }
+            };
+            for row in rows {
+                if sender.blocking_send(row).is_err() {
+                    break;
+                }
+            }
+        };
+        if self.jobs.send(Box::new(job)).is_err() {
+            let _ = closed.try_send(Err(closed_connection()));
+        }
+
+        DuckDbRowStream { receiver }
+    }
+
+    /// Run `f` inside a transaction on the worker thread
+    ///
+    /// The transaction is committed if `f` returns `Ok` and rolled back otherwise.
+    pub async fn transaction<R, E, F>(&self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut DuckDbConnection) -> Result<R, E> + Send + 'static,
+        E: From<DieselError> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.run(move |connection| connection.transaction(f))
+            .await?
+    }
+}
+
+/// Rows of a query run by [`AsyncDuckDbConnection::load_buffered_stream`]
+pub struct DuckDbRowStream<U> {
+    receiver: tokio::sync::mpsc::Receiver<QueryResult<U>>,
+}
+
+impl<U> futures_core::Stream for DuckDbRowStream<U> {
+    type Item = QueryResult<U>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+fn closed_connection() -> DieselError {
+    DieselError::DatabaseError(
+        DatabaseErrorKind::ClosedConnection,
+        Box::new(DuckDbErrorInformation {
+            error_message: "Connection Error: DuckDB worker thread has stopped".to_string(),
+            table_name: None,
+            column_name: None,
+            constraint_name: None,
+            statement_position: None,
+            details: None,
+            hint: None,
+        }),
+    )
+}
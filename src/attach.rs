@@ -0,0 +1,167 @@
+use diesel::connection::SimpleConnection;
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
+use diesel::{QueryResult, Table};
+
+use crate::table_source::{FromClauseSource, TableSource};
+use crate::{DuckDb, DuckDbConnection};
+
+/// Options for attaching another database with [`DuckDbConnection::attach`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttachOptions {
+    read_only: bool,
+    database_type: Option<String>,
+}
+
+impl AttachOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach the database for reading only
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Storage type of the attached database, e.g. `"sqlite"` (needs the matching extension)
+    pub fn database_type(mut self, database_type: impl Into<String>) -> Self {
+        self.database_type = Some(database_type.into());
+        self
+    }
+
+    fn to_sql(&self) -> String {
+        let mut options = Vec::new();
+        if self.read_only {
+            options.push("READ_ONLY".to_string());
+        }
+        if let Some(database_type) = &self.database_type {
+            options.push(format!("TYPE {}", quote_identifier(database_type)));
+        }
+
+        if options.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", options.join(", "))
+        }
+    }
+}
+
+impl DuckDbConnection {
+    /// Attach the database at `path` under the catalog name `alias`
+    ///
+    /// Tables in the `main` schema of the attached database can then be
+    /// declared as `alias.table` in `table!`, tables in another of its schemas
+    /// with [`catalog_table!`](crate::catalog_table!).
+    pub fn attach(&mut self, path: &str, alias: &str, options: AttachOptions) -> QueryResult<()> {
+        self.batch_execute(&format!(
+            "ATTACH {} AS {}{}",
+            quote_literal(path),
+            quote_identifier(alias),
+            options.to_sql()
+        ))
+    }
+
+    /// Detach the database attached as `alias`
+    pub fn detach(&mut self, alias: &str) -> QueryResult<()> {
+        self.batch_execute(&format!("DETACH {}", quote_identifier(alias)))
+    }
+}
+
+/// Declare a table of another schema of an attached database, with the
+/// syntax of `table!` and a `catalog.schema.table` path
+///
+/// The table is queried through [`CatalogTable::in_catalog`], which names it
+/// as `"catalog"."schema"."table"`. Used directly, it is the table `table` of
+/// the schema `schema` of the default database. The qualified table can be
+/// read, filtered and joined to other tables with an explicit `ON` clause;
+/// writes are not supported.
+///
+/// ```ignore
+/// diesel_duckdb::catalog_table! {
+///     analytics.archive.events (event_id) {
+///         event_id -> Integer,
+///         kind -> Text,
+///     }
+/// }
+///
+/// let kinds = events::table
+///     .in_catalog()
+///     .select(events::kind)
+///     .load::<String>(&mut conn)?;
+/// ```
+#[macro_export]
+macro_rules! catalog_table {
+    (
+        $(#[$meta:meta])*
+        $catalog:ident . $schema:ident . $name:ident $(($($pk:ident),+ $(,)?))? {
+            $($(#[$column_meta:meta])* $column:ident -> $column_type:ty),+ $(,)?
+        }
+    ) => {
+        diesel::table! {
+            $(#[$meta])*
+            $schema.$name $(($($pk),+))? {
+                $($(#[$column_meta])* $column -> $column_type,)+
+            }
+        }
+
+        impl $crate::attach::CatalogTable for $name::table {
+            const CATALOG: &'static str = stringify!($catalog);
+        }
+
+        impl diesel::query_source::AppearsInFromClause<$name::table>
+            for $crate::attach::InCatalog<$name::table>
+        {
+            type Count = diesel::query_source::Once;
+        }
+
+        $(
+            impl diesel::SelectableExpression<$crate::attach::InCatalog<$name::table>>
+                for $name::$column
+            {
+            }
+        )+
+    };
+}
+
+/// A table declared with [`catalog_table!`](crate::catalog_table!)
+pub trait CatalogTable: Table + Clone {
+    /// Name of the attached database holding the table
+    const CATALOG: &'static str;
+
+    /// The table, qualified with the name of its database
+    fn in_catalog(self) -> InCatalog<Self> {
+        TableSource::new(self, AttachedCatalog)
+    }
+}
+
+/// A table qualified with the name of the attached database holding it, see
+/// [`CatalogTable::in_catalog`]
+pub type InCatalog<T> = TableSource<T, AttachedCatalog>;
+
+/// Writes the `FROM` clause of an [`InCatalog`] table as
+/// `"catalog"."schema"."table"`
+#[derive(Debug, Clone, Copy, Default, QueryId)]
+pub struct AttachedCatalog;
+
+impl<T> FromClauseSource<T> for AttachedCatalog
+where
+    T: CatalogTable + QueryFragment<DuckDb>,
+{
+    fn walk_from_clause<'b>(
+        &'b self,
+        table: &'b T,
+        mut out: AstPass<'_, 'b, DuckDb>,
+    ) -> QueryResult<()> {
+        out.push_identifier(T::CATALOG)?;
+        out.push_sql(".");
+        table.walk_ast(out.reborrow())
+    }
+}
+
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
@@ -6,7 +6,7 @@ use diesel::sql_types::{HasSqlType, TypeMetadata};
 
 use crate::query_builder::DuckDBQueryBuilder;
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
 pub struct DuckDb;
 
 impl Backend for DuckDb {
@@ -39,6 +39,10 @@ impl TypeMetadata for DuckDb {
     type MetadataLookup = ();
 }
 
+impl HasSqlType<diesel::sql_types::TinyInt> for DuckDb {
+    fn metadata(_: &mut ()) -> Self::TypeMetadata {}
+}
+
 impl HasSqlType<diesel::sql_types::SmallInt> for DuckDb {
     fn metadata(_: &mut ()) -> Self::TypeMetadata {}
 }
@@ -0,0 +1,59 @@
+//! Connection pooling with bb8
+
+use diesel::result::ConnectionResult;
+
+pub use crate::pool::PoolError;
+use crate::pool::{recycle, ConnectionFactory};
+use crate::{AsyncDuckDbConnection, DuckDbConnectionBuilder};
+
+pub type Pool = bb8::Pool<DuckDbConnectionManager>;
+
+/// bb8 manager for [`AsyncDuckDbConnection`]s
+///
+/// Validating a checked out connection rolls back transactions left open
+/// and checks the connection with `SELECT 1`. A connection handed back with
+/// a transaction still open is dropped instead of returned to the pool.
+#[derive(Debug)]
+pub struct DuckDbConnectionManager {
+    factory: ConnectionFactory,
+}
+
+impl DuckDbConnectionManager {
+    /// Open every pooled connection from `builder`
+    pub fn new(builder: DuckDbConnectionBuilder) -> Self {
+        Self {
+            factory: ConnectionFactory::new(builder),
+        }
+    }
+
+    /// Open the database once and hand out clones of that connection, see
+    /// [`AsyncDuckDbConnection::try_clone`]
+    pub fn shared(builder: DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        Ok(Self {
+            factory: ConnectionFactory::shared(builder)?,
+        })
+    }
+
+    /// SQL run on every new connection, e.g. `SET threads = 4` or `LOAD httpfs`
+    pub fn with_init_sql(mut self, sql: impl Into<String>) -> Self {
+        self.factory.add_init_sql(sql.into());
+        self
+    }
+}
+
+impl bb8::ManageConnection for DuckDbConnectionManager {
+    type Connection = AsyncDuckDbConnection;
+    type Error = PoolError;
+
+    async fn connect(&self) -> Result<AsyncDuckDbConnection, PoolError> {
+        self.factory.connect().await
+    }
+
+    async fn is_valid(&self, connection: &mut AsyncDuckDbConnection) -> Result<(), PoolError> {
+        recycle(connection).await.map_err(PoolError::QueryError)
+    }
+
+    fn has_broken(&self, connection: &mut AsyncDuckDbConnection) -> bool {
+        std::thread::panicking() || connection.is_in_transaction()
+    }
+}
@@ -12,6 +12,10 @@ pub struct DuckDbBindCollector<'a> {
 }
 
 impl<'a> DuckDbBindCollector<'a> {
+    pub fn bind_count(&self) -> usize {
+        self.binds.len()
+    }
+
     pub fn into_params(self) -> ParamsFromIter<Vec<ToSqlOutput<'a>>> {
         params_from_iter(self.binds)
     }
@@ -1,7 +1,8 @@
 use diesel::{
     connection::{
-        get_default_instrumentation, statement_cache::StatementCache, AnsiTransactionManager,
-        ConnectionSealed, DefaultLoadingMode, Instrumentation, LoadConnection, SimpleConnection,
+        get_default_instrumentation, statement_cache::StatementCache, ConnectionSealed,
+        DefaultLoadingMode, Instrumentation, InstrumentationEvent, LoadConnection,
+        SimpleConnection, StrQueryHelper, TransactionManager,
     },
     expression::QueryMetadata,
     migration::{MigrationConnection, CREATE_MIGRATIONS_TABLE},
@@ -10,12 +11,23 @@ use diesel::{
     row::{Field, PartialRow, Row, RowIndex, RowSealed},
     sql_query, Connection, QueryResult, RunQueryDsl,
 };
-use duckdb::Connection as DuckDBConn;
-
-use crate::error::MapDieselError;
-use crate::{bind_collector::DuckDbBindCollector, DuckDb};
-use diesel::connection::statement_cache::MaybeCached;
+use duckdb::types::{ToSqlOutput, ValueRef};
+use duckdb::{Connection as DuckDBConn, ParamsFromIter};
+
+use crate::database::{self, Database};
+use crate::error::{DuckDbErrorInformation, MapDieselError, MapQueryError};
+use crate::interrupt::{timed_out, Watchdog};
+#[cfg(feature = "vtab")]
+use crate::registry::Registration;
+use crate::tracing_support::{record_binds, record_rows, record_statement, QuerySpan};
+use crate::transaction_manager::DuckDbTransactionManager;
+use crate::{bind_collector::DuckDbBindCollector, DuckDb, DuckDbConnectionBuilder};
+use diesel::connection::statement_cache::{MaybeCached, PrepareForCache};
+#[cfg(feature = "vtab")]
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
 
 // Cursor type for iterating over query results
 pub struct DuckDbCursor<'conn, 'query> {
@@ -43,7 +55,7 @@ impl<'conn, 'query> Iterator for DuckDbCursor<'conn, 'query> {
 // Row type for individual database rows
 pub struct DuckDbRow<'conn, 'query> {
     // Store the raw data instead of the duckdb::Row directly
-    values: Vec<duckdb::types::Value>,
+    values: Vec<RowValue>,
     column_names: Vec<String>,
     _phantom: PhantomData<(&'conn (), &'query ())>,
 }
@@ -57,9 +69,9 @@ impl<'conn, 'query> DuckDbRow<'conn, 'query> {
         for i in 0..column_count {
             // Extract the value
             let value = row
-                .get::<_, duckdb::types::Value>(i)
+                .get_ref(i)
                 .map_err(|e| diesel::result::Error::DeserializationError(e.into()))?;
-            values.push(value);
+            values.push(RowValue::new(value));
 
             // Extract the column name
             let name = row
@@ -77,6 +89,24 @@ impl<'conn, 'query> DuckDbRow<'conn, 'query> {
     }
 }
 
+// Owned copy of a column value
+//
+// `ValueRef::to_owned` panics on text that isn't valid UTF-8, so text is kept
+// as bytes and handed to diesel borrowed, leaving the check to `raw_value`.
+enum RowValue {
+    Text(Vec<u8>),
+    Value(duckdb::types::Value),
+}
+
+impl RowValue {
+    fn new(value: ValueRef<'_>) -> Self {
+        match value {
+            ValueRef::Text(bytes) => Self::Text(bytes.to_vec()),
+            value => Self::Value(value.to_owned()),
+        }
+    }
+}
+
 impl<'conn, 'query> RowSealed for DuckDbRow<'conn, 'query> {}
 
 impl<'conn, 'query> Row<'conn, DuckDb> for DuckDbRow<'conn, 'query> {
@@ -144,14 +174,15 @@ impl<'row> Field<'row, DuckDb> for DuckDbField<'row> {
     fn is_null(&self) -> bool {
         matches!(
             self.row.values.get(self.idx),
-            Some(duckdb::types::Value::Null)
+            Some(RowValue::Value(duckdb::types::Value::Null))
         )
     }
 
     fn value(&self) -> Option<<DuckDb as diesel::backend::Backend>::RawValue<'_>> {
         match self.row.values.get(self.idx) {
-            Some(duckdb::types::Value::Null) => None,
-            Some(value) => Some(duckdb::types::ToSqlOutput::Owned(value.clone())),
+            Some(RowValue::Value(duckdb::types::Value::Null)) => None,
+            Some(RowValue::Value(value)) => Some(ToSqlOutput::Owned(value.clone())),
+            Some(RowValue::Text(bytes)) => Some(ToSqlOutput::Borrowed(ValueRef::Text(bytes))),
             None => None,
         }
     }
@@ -160,24 +191,365 @@ impl<'row> Field<'row, DuckDb> for DuckDbField<'row> {
 pub struct DuckDbConnection {
     statement_cache: StatementCache<DuckDb, String>,
     connection: DuckDBConn,
-    transaction_state: AnsiTransactionManager,
+    // The database of `connection` when opened through a builder, shared
+    // with the connections made by `try_clone`; declared after `connection`
+    // to be closed after it
+    pub(crate) database: Option<Arc<Database>>,
+    transaction_state: DuckDbTransactionManager,
     instrumentation: Option<Box<dyn Instrumentation>>,
+    // Set when opened through a builder, used by `reopen`
+    builder: Option<DuckDbConnectionBuilder>,
+    // Set by `try_clone`, the database belongs to another connection
+    cloned: bool,
+    // Set when `reopen` closed the database and failed to open it again
+    closed: bool,
+    read_only: bool,
+    query_timeout: Option<Duration>,
+    // Started with the first query run under a timeout
+    watchdog: Option<Watchdog>,
+    // Token of the connection in the registry, set on first registration
+    #[cfg(feature = "vtab")]
+    pub(crate) registry_owner: Option<String>,
+    // Rows behind the temporary views made by `register_virtual_table`
+    #[cfg(feature = "vtab")]
+    pub(crate) virtual_tables: BTreeMap<String, Registration>,
+    // Closures behind the temporary macros made by `register_scalar_function`
+    #[cfg(feature = "vscalar")]
+    pub(crate) scalar_functions: BTreeMap<String, Registration>,
+    // Aggregates behind the temporary macros made by `register_aggregate_function`
+    #[cfg(feature = "vscalar")]
+    pub(crate) aggregate_functions: BTreeMap<String, Registration>,
 }
 
 impl DuckDbConnection {
     pub fn establish_with_flags(database_url: &str, config: duckdb::Config) -> ConnectionResult<Self> {
-        let instrumentation = get_default_instrumentation();
+        Self::establish_instrumented(database_url, || Self::open(database_url, config))
+    }
+
+    pub(crate) fn establish_with_builder(builder: &DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        Self::establish_instrumented(builder.path(), || Self::open_with_builder(builder))
+    }
 
+    // Emit the establish connection events around `open` and keep the
+    // instrumentation that saw them
+    fn establish_instrumented(
+        database_url: &str,
+        open: impl FnOnce() -> ConnectionResult<Self>,
+    ) -> ConnectionResult<Self> {
+        let mut instrumentation = get_default_instrumentation();
+        instrumentation.on_connection_event(InstrumentationEvent::start_establish_connection(
+            database_url,
+        ));
+
+        let result = open();
+        instrumentation.on_connection_event(InstrumentationEvent::finish_establish_connection(
+            database_url,
+            result.as_ref().err(),
+        ));
+
+        let mut connection = result?;
+        connection.instrumentation = instrumentation;
+        Ok(connection)
+    }
+
+    fn open(database_url: &str, config: duckdb::Config) -> ConnectionResult<Self> {
         let conn_result = DuckDBConn::open_with_flags(database_url, config);
         let connection = conn_result.map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
 
-        Ok(Self {
+        Ok(Self::from_duckdb_connection(connection, false))
+    }
+
+    fn open_with_builder(builder: &DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        let (connection, database) = database::open(builder.path(), &builder.settings())?;
+        let mut connection = Self::from_duckdb_connection(connection, false);
+        connection.database = Some(database);
+        connection.read_only = builder.is_read_only();
+        connection.query_timeout = builder.timeout();
+        connection.builder = Some(builder.clone());
+        Ok(connection)
+    }
+
+    // Wrap an open duckdb connection with fresh diesel connection state
+    fn from_duckdb_connection(connection: DuckDBConn, read_only: bool) -> Self {
+        Self {
             connection,
-            transaction_state: AnsiTransactionManager::default(),
-            instrumentation,
+            database: None,
+            transaction_state: DuckDbTransactionManager::default(),
+            instrumentation: None,
             statement_cache: StatementCache::new(),
+            builder: None,
+            cloned: false,
+            closed: false,
+            read_only,
+            query_timeout: None,
+            watchdog: None,
+            #[cfg(feature = "vtab")]
+            registry_owner: None,
+            #[cfg(feature = "vtab")]
+            virtual_tables: BTreeMap::new(),
+            #[cfg(feature = "vscalar")]
+            scalar_functions: BTreeMap::new(),
+            #[cfg(feature = "vscalar")]
+            aggregate_functions: BTreeMap::new(),
+        }
+    }
+
+    /// Start configuring a connection to the database at `path`
+    pub fn builder(path: impl Into<String>) -> DuckDbConnectionBuilder {
+        DuckDbConnectionBuilder::new(path)
+    }
+
+    /// Open another connection to the same database
+    ///
+    /// The new connection has its own statement cache and transaction state,
+    /// so it can be moved to another thread and used in parallel; this is the
+    /// only way to share an in-memory database between connections.
+    pub fn try_clone(&self) -> ConnectionResult<Self> {
+        self.check_open()
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        let connection = self
+            .connection
+            .try_clone()
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+
+        let mut clone = Self::from_duckdb_connection(connection, self.read_only);
+        clone.instrumentation = get_default_instrumentation();
+        clone.builder = self.builder.clone();
+        clone.database = self.database.clone();
+        clone.cloned = true;
+        clone.query_timeout = self.query_timeout;
+        Ok(clone)
+    }
+
+    /// Whether the database was opened with `access_mode=read_only`
+    ///
+    /// DuckDB rejects statements writing to the database of a read-only
+    /// connection, which fail with `DatabaseErrorKind::ReadOnlyTransaction`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Abort queries running longer than `timeout`, or never with `None`
+    ///
+    /// A query that times out fails with an error of the kind
+    /// [`DuckDbErrorKind::Timeout`](crate::DuckDbErrorKind::Timeout), see
+    /// [`DuckDbErrorExt::is_timeout`](crate::DuckDbErrorExt::is_timeout).
+    pub fn set_query_timeout(&mut self, timeout: Option<Duration>) {
+        self.query_timeout = timeout;
+    }
+
+    pub fn query_timeout(&self) -> Option<Duration> {
+        self.query_timeout
+    }
+
+    /// Close and re-open the database with the same options
+    ///
+    /// A read-only connection sees the state of the database file as of when
+    /// it was opened; reopening picks up changes checkpointed by a writer since.
+    /// Only connections to a database file opened through
+    /// [`DuckDbConnectionBuilder`] or [`Connection::establish`] can be
+    /// reopened, not in-memory databases (reopening would lose their data) or
+    /// connections made by [`try_clone`](Self::try_clone), and not inside a
+    /// transaction.
+    ///
+    /// A read-only database is opened again before the old one is closed, so
+    /// on failure the connection keeps working with the old one. A writable
+    /// database can't be opened twice and is closed first; if opening it again
+    /// fails, every later query fails until `reopen` succeeds.
+    pub fn reopen(&mut self) -> ConnectionResult<()> {
+        let builder = self.builder.clone().ok_or_else(|| {
+            ConnectionError::BadConnection(
+                "Cannot reopen a connection opened with a raw duckdb::Config".to_string(),
+            )
+        })?;
+        if is_in_memory(builder.path()) {
+            return Err(ConnectionError::BadConnection(
+                "Cannot reopen an in-memory database".to_string(),
+            ));
+        }
+        if self.cloned {
+            return Err(ConnectionError::BadConnection(
+                "Cannot reopen a connection made by try_clone".to_string(),
+            ));
+        }
+        let depth = DuckDbTransactionManager::transaction_manager_status_mut(self)
+            .transaction_depth()
+            .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+        if depth.is_some() {
+            return Err(ConnectionError::BadConnection(
+                "Cannot reopen a connection inside a transaction".to_string(),
+            ));
+        }
+
+        self.instrumentation
+            .on_connection_event(InstrumentationEvent::start_establish_connection(
+                builder.path(),
+            ));
+        let result = self.reopen_with(&builder);
+        self.instrumentation
+            .on_connection_event(InstrumentationEvent::finish_establish_connection(
+                builder.path(),
+                result.as_ref().err(),
+            ));
+        result
+    }
+
+    fn reopen_with(&mut self, builder: &DuckDbConnectionBuilder) -> ConnectionResult<()> {
+        let open = || database::open(builder.path(), &builder.settings());
+        let (connection, database) = if builder.is_read_only() {
+            open()?
+        } else {
+            // Release the old database and its file lock before opening it
+            // again, with a placeholder until then
+            let placeholder = DuckDBConn::open_in_memory()
+                .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
+            drop(std::mem::replace(&mut self.connection, placeholder));
+            self.database = None;
+            self.closed = true;
+            open()?
+        };
+        self.connection = connection;
+        self.database = Some(database);
+        self.closed = false;
+        self.statement_cache = StatementCache::new();
+        // the watchdog would interrupt the old connection
+        self.watchdog = None;
+        self.transaction_state = DuckDbTransactionManager::default();
+        #[cfg(feature = "vtab")]
+        {
+            // a new session, without the variable holding the token
+            self.registry_owner = None;
+            self.virtual_tables.clear();
+        }
+        #[cfg(feature = "vscalar")]
+        self.scalar_functions.clear();
+        #[cfg(feature = "vscalar")]
+        self.aggregate_functions.clear();
+        Ok(())
+    }
+
+    // Fail if `reopen` left the connection without a database
+    fn check_open(&self) -> QueryResult<()> {
+        if self.closed {
+            return Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ClosedConnection,
+                Box::new(DuckDbErrorInformation::from_message(
+                    "Connection Error: The database could not be reopened".to_string(),
+                )),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// DuckDB opens an in-memory database for an empty path or `:memory:[name]`
+fn is_in_memory(path: &str) -> bool {
+    path.is_empty() || path.starts_with(":memory:")
+}
+
+impl DuckDbConnection {
+    // Prepare `source` and run `f` with the statement and its bind
+    // parameters, emitting the start and finish query events around it
+    fn run_query<T, R>(
+        &mut self,
+        source: &T,
+        f: impl FnOnce(&mut duckdb::Statement<'_>, ParamsFromIter<Vec<ToSqlOutput<'_>>>) -> QueryResult<R>,
+    ) -> QueryResult<R>
+    where
+        T: QueryFragment<DuckDb> + QueryId,
+    {
+        QuerySpan::new().in_scope(|| {
+            self.instrumentation
+                .on_connection_event(InstrumentationEvent::start_query(&diesel::debug_query(
+                    source,
+                )));
+            let result = self.with_watchdog(|conn| conn.prepare_and_run(source, f));
+            self.instrumentation
+                .on_connection_event(InstrumentationEvent::finish_query(
+                    &diesel::debug_query(source),
+                    result.as_ref().err(),
+                ));
+            result
         })
     }
+
+    // Run `f` under the query timeout, if there is one
+    fn with_watchdog<R>(&mut self, f: impl FnOnce(&mut Self) -> QueryResult<R>) -> QueryResult<R> {
+        self.check_open()?;
+        let Some(timeout) = self.query_timeout else {
+            return f(self);
+        };
+        let watchdog = match &mut self.watchdog {
+            Some(watchdog) => watchdog,
+            watchdog @ None => watchdog.insert(Watchdog::new(self.connection.interrupt_handle())?),
+        };
+        watchdog.arm(timeout);
+
+        let result = f(self);
+        let fired = self.watchdog.as_ref().is_some_and(Watchdog::disarm);
+        if fired {
+            timed_out(result)
+        } else {
+            result
+        }
+    }
+
+    fn prepare_and_run<T, R>(
+        &mut self,
+        source: &T,
+        f: impl FnOnce(&mut duckdb::Statement<'_>, ParamsFromIter<Vec<ToSqlOutput<'_>>>) -> QueryResult<R>,
+    ) -> QueryResult<R>
+    where
+        T: QueryFragment<DuckDb> + QueryId,
+    {
+        // Statements are only prepared on a cache miss or if they can't be cached
+        let mut cache_hit = Some(true);
+        let stmt = self.statement_cache.cached_statement(
+            source,
+            &DuckDb,
+            &[],
+            |sql, for_cache| {
+                cache_hit = match for_cache {
+                    PrepareForCache::Yes => Some(false),
+                    _ => None,
+                };
+                Ok(sql.to_owned()) // hack, passthrough and let underlying duckdb library do it
+            },
+            &mut self.instrumentation,
+        )?;
+        record_statement(&stmt, cache_hit);
+
+        let mut binds = DuckDbBindCollector::default();
+        source.collect_binds(&mut binds, &mut (), &DuckDb)?;
+        record_binds(binds.bind_count());
+        let params = binds.into_params();
+
+        with_prepared_statement(&self.connection, stmt, |q| f(q, params))
+    }
+}
+
+/// Prepare the SQL handed out by the statement cache and run `f` with it
+///
+/// Cached entries go through duckdb's own prepared statement cache, uncached
+/// ones are prepared afresh.
+fn with_prepared_statement<R>(
+    connection: &DuckDBConn,
+    stmt: MaybeCached<'_, String>,
+    f: impl FnOnce(&mut duckdb::Statement<'_>) -> QueryResult<R>,
+) -> QueryResult<R> {
+    match stmt {
+        MaybeCached::Cached(sql) => {
+            let mut q = connection.prepare_cached(sql).map_query_error(sql)?;
+            f(&mut q)
+        }
+        MaybeCached::CannotCache(sql) => {
+            let mut q = connection.prepare(&sql).map_query_error(&sql)?;
+            f(&mut q)
+        }
+        _ => Err(diesel::result::Error::QueryBuilderError(
+            "Unsupported statement cache entry".into(),
+        )),
+    }
 }
 
 impl AsRef<DuckDBConn> for DuckDbConnection {
@@ -200,41 +572,16 @@ impl LoadConnection<DefaultLoadingMode> for DuckDbConnection {
         T: Query + QueryFragment<Self::Backend> + QueryId + 'query,
         Self::Backend: QueryMetadata<T::SqlType>,
     {
-        let stmt = self.statement_cache.cached_statement(
-            &source,
-            &DuckDb,
-            &[],
-            |sql, _| Ok(sql.to_owned()),
-            &mut self.instrumentation,
-        )?;
+        let rows = self.run_query(&source, |q, params| {
+            let mut rows = q.query(params).map_diesel_error()?;
+            let mut result_rows = Vec::new();
 
-        let mut binds = DuckDbBindCollector::default();
-        source.collect_binds(&mut binds, &mut (), &DuckDb)?;
-        let params = binds.into_params();
-
-        let rows = match stmt {
-            MaybeCached::Cached(sql) => {
-                let mut q = self.connection.prepare_cached(sql).map_diesel_error()?;
-                let mut rows = q.query(params).map_diesel_error()?;
-                let mut result_rows = Vec::new();
-
-                while let Some(row) = rows.next().map_diesel_error()? {
-                    result_rows.push(DuckDbRow::from_duckdb_row(row)?);
-                }
-                result_rows
-            }
-            MaybeCached::CannotCache(sql) => {
-                let mut q = self.connection.prepare(&sql).map_diesel_error()?;
-                let mut rows = q.query(params).map_diesel_error()?;
-                let mut result_rows = Vec::new();
-
-                while let Some(row) = rows.next().map_diesel_error()? {
-                    result_rows.push(DuckDbRow::from_duckdb_row(row)?);
-                }
-                result_rows
+            while let Some(row) = rows.next().map_diesel_error()? {
+                result_rows.push(DuckDbRow::from_duckdb_row(row)?);
             }
-            _ => panic!("Unexpected statement cache state"),
-        };
+            record_rows(result_rows.len());
+            Ok(result_rows)
+        })?;
 
         Ok(DuckDbCursor::new(rows))
     }
@@ -242,56 +589,46 @@ impl LoadConnection<DefaultLoadingMode> for DuckDbConnection {
 
 impl SimpleConnection for DuckDbConnection {
     fn batch_execute(&mut self, query: &str) -> diesel::QueryResult<()> {
-        self.connection.execute_batch(query).map_diesel_error()
+        QuerySpan::new().in_scope(|| {
+            record_statement(query, None);
+            self.instrumentation
+                .on_connection_event(InstrumentationEvent::start_query(&StrQueryHelper::new(
+                    query,
+                )));
+            let result = self
+                .with_watchdog(|conn| conn.connection.execute_batch(query).map_query_error(query));
+            self.instrumentation
+                .on_connection_event(InstrumentationEvent::finish_query(
+                    &StrQueryHelper::new(query),
+                    result.as_ref().err(),
+                ));
+            result
+        })
     }
 }
 
 impl Connection for DuckDbConnection {
     type Backend = DuckDb;
-    type TransactionManager = AnsiTransactionManager;
+    type TransactionManager = DuckDbTransactionManager;
 
     fn establish(database_url: &str) -> ConnectionResult<Self> {
-        let config = duckdb::Config::default();
-        Self::establish_with_flags(database_url, config)
+        Self::establish_instrumented(database_url, || {
+            Self::open_with_builder(&DuckDbConnectionBuilder::from_url(database_url)?)
+        })
     }
 
     fn execute_returning_count<T>(&mut self, source: &T) -> QueryResult<usize>
     where
         T: QueryFragment<Self::Backend> + QueryId,
     {
-        // self.instrumentation
-        //     .on_connection_event(InstrumentationEvent::StartQuery {
-        //         query: &diesel::debug_query(&source),
-        //     });
-
-        let stmt = self.statement_cache.cached_statement(
-            &source,
-            &DuckDb,
-            &[],
-            |sql, _| Ok(sql.to_owned()), // hack, passthrough and let underlying duckdb library do it
-            &mut self.instrumentation,
-        )?;
-
-        let mut binds = DuckDbBindCollector::default();
-        source.collect_binds(&mut binds, &mut (), &DuckDb)?;
-        let params = binds.into_params();
-
-        let count = match stmt {
-            MaybeCached::Cached(sql) => {
-                let mut q = self.connection.prepare_cached(sql).map_diesel_error()?;
-                q.execute(params).map_diesel_error()?
-            }
-            MaybeCached::CannotCache(sql) => {
-                let mut q = self.connection.prepare(&sql).map_diesel_error()?;
-                q.execute(params).map_diesel_error()?
-            }
-            _ => panic!("Unexpected statement cache state"),
-        };
-
-        Ok(count)
+        self.run_query(source, |q, params| {
+            let count = q.execute(params).map_diesel_error()?;
+            record_rows(count);
+            Ok(count)
+        })
     }
 
-    fn transaction_state(&mut self) -> &mut AnsiTransactionManager {
+    fn transaction_state(&mut self) -> &mut DuckDbTransactionManager {
         &mut self.transaction_state
     }
 
@@ -0,0 +1,313 @@
+use std::time::Duration;
+
+use diesel::result::{ConnectionError, ConnectionResult};
+
+use crate::DuckDbConnection;
+
+/// Whether the database is opened for reading and writing or for reading only
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessMode {
+    Automatic,
+    ReadOnly,
+    ReadWrite,
+}
+
+impl AccessMode {
+    fn as_setting(self) -> &'static str {
+        match self {
+            AccessMode::Automatic => "AUTOMATIC",
+            AccessMode::ReadOnly => "READ_ONLY",
+            AccessMode::ReadWrite => "READ_WRITE",
+        }
+    }
+}
+
+/// Direction used by `ORDER BY` clauses without an explicit `ASC`/`DESC`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultOrder {
+    Asc,
+    Desc,
+}
+
+impl DefaultOrder {
+    fn as_setting(self) -> &'static str {
+        match self {
+            DefaultOrder::Asc => "ASC",
+            DefaultOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Placement of NULLs by `ORDER BY` clauses without an explicit `NULLS FIRST`/`NULLS LAST`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullOrder {
+    NullsFirst,
+    NullsLast,
+}
+
+impl NullOrder {
+    fn as_setting(self) -> &'static str {
+        match self {
+            NullOrder::NullsFirst => "NULLS_FIRST",
+            NullOrder::NullsLast => "NULLS_LAST",
+        }
+    }
+}
+
+/// Builder for a [`DuckDbConnection`] with typed configuration options
+///
+/// The same options can be given as query parameters of the database URL
+/// passed to [`Connection::establish`](diesel::Connection::establish), e.g.
+/// `file.duckdb?threads=4&access_mode=read_only`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DuckDbConnectionBuilder {
+    path: String,
+    access_mode: Option<AccessMode>,
+    threads: Option<u32>,
+    memory_limit: Option<String>,
+    temp_directory: Option<String>,
+    max_temp_directory_size: Option<String>,
+    default_order: Option<DefaultOrder>,
+    default_null_order: Option<NullOrder>,
+    enable_external_access: Option<bool>,
+    allow_unsigned_extensions: Option<bool>,
+    query_timeout: Option<Duration>,
+}
+
+impl DuckDbConnectionBuilder {
+    /// Create a builder for the database at `path` (or `:memory:`)
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Create a builder from a database URL with optional query parameters
+    pub fn from_url(database_url: &str) -> ConnectionResult<Self> {
+        let (path, query) = match database_url.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (database_url, ""),
+        };
+        let mut builder = Self::new(path);
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            builder = builder.with_url_parameter(key, &value)?;
+        }
+
+        Ok(builder)
+    }
+
+    fn with_url_parameter(self, key: &str, value: &str) -> ConnectionResult<Self> {
+        let builder = match key {
+            "access_mode" => self.access_mode(match value.to_ascii_lowercase().as_str() {
+                "automatic" => AccessMode::Automatic,
+                "read_only" => AccessMode::ReadOnly,
+                "read_write" => AccessMode::ReadWrite,
+                _ => return Err(invalid_parameter(key, value)),
+            }),
+            "threads" => self.threads(value.parse().map_err(|_| invalid_parameter(key, value))?),
+            "memory_limit" => self.memory_limit(value),
+            "temp_directory" => self.temp_directory(value),
+            "max_temp_directory_size" => self.max_temp_directory_size(value),
+            "default_order" => self.default_order(match value.to_ascii_lowercase().as_str() {
+                "asc" => DefaultOrder::Asc,
+                "desc" => DefaultOrder::Desc,
+                _ => return Err(invalid_parameter(key, value)),
+            }),
+            "default_null_order" => {
+                self.default_null_order(match value.to_ascii_lowercase().as_str() {
+                    "nulls_first" => NullOrder::NullsFirst,
+                    "nulls_last" => NullOrder::NullsLast,
+                    _ => return Err(invalid_parameter(key, value)),
+                })
+            }
+            "enable_external_access" => self.enable_external_access(
+                parse_bool(value).ok_or_else(|| invalid_parameter(key, value))?,
+            ),
+            "allow_unsigned_extensions" => self.allow_unsigned_extensions(
+                parse_bool(value).ok_or_else(|| invalid_parameter(key, value))?,
+            ),
+            "query_timeout_ms" => self.query_timeout(Duration::from_millis(
+                value.parse().map_err(|_| invalid_parameter(key, value))?,
+            )),
+            _ => {
+                return Err(ConnectionError::InvalidConnectionUrl(format!(
+                    "Unknown DuckDB connection parameter: {}",
+                    key
+                )))
+            }
+        };
+        Ok(builder)
+    }
+
+    /// Path of the database this builder connects to
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn access_mode(mut self, access_mode: AccessMode) -> Self {
+        self.access_mode = Some(access_mode);
+        self
+    }
+
+    /// Shorthand for `access_mode(AccessMode::ReadOnly)`
+    pub fn read_only(self) -> Self {
+        self.access_mode(AccessMode::ReadOnly)
+    }
+
+    pub fn threads(mut self, threads: u32) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Maximum memory of the database, e.g. `"4GB"`
+    pub fn memory_limit(mut self, memory_limit: impl Into<String>) -> Self {
+        self.memory_limit = Some(memory_limit.into());
+        self
+    }
+
+    /// Directory used to spill data that does not fit into memory
+    pub fn temp_directory(mut self, temp_directory: impl Into<String>) -> Self {
+        self.temp_directory = Some(temp_directory.into());
+        self
+    }
+
+    /// Maximum size of the temp directory, e.g. `"10GB"`
+    pub fn max_temp_directory_size(mut self, size: impl Into<String>) -> Self {
+        self.max_temp_directory_size = Some(size.into());
+        self
+    }
+
+    pub fn default_order(mut self, order: DefaultOrder) -> Self {
+        self.default_order = Some(order);
+        self
+    }
+
+    pub fn default_null_order(mut self, order: NullOrder) -> Self {
+        self.default_null_order = Some(order);
+        self
+    }
+
+    /// Whether queries may access files and the network (default `true`)
+    pub fn enable_external_access(mut self, enabled: bool) -> Self {
+        self.enable_external_access = Some(enabled);
+        self
+    }
+
+    pub fn allow_unsigned_extensions(mut self, allowed: bool) -> Self {
+        self.allow_unsigned_extensions = Some(allowed);
+        self
+    }
+
+    /// Abort queries running longer than `timeout`, see
+    /// [`DuckDbConnection::set_query_timeout`]
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Build the `duckdb::Config` for the configured options
+    pub fn config(&self) -> ConnectionResult<duckdb::Config> {
+        self.settings()
+            .into_iter()
+            .try_fold(duckdb::Config::default(), |config, (key, value)| {
+                config
+                    .with(key, &value)
+                    .map_err(|e| ConnectionError::BadConnection(e.to_string()))
+            })
+    }
+
+    // The DuckDB settings of the options that are set, as (key, value)
+    pub(crate) fn settings(&self) -> Vec<(&'static str, String)> {
+        let bool_setting = |enabled: bool| if enabled { "true" } else { "false" };
+        let settings = [
+            (
+                "access_mode",
+                self.access_mode.map(|m| m.as_setting().to_string()),
+            ),
+            ("threads", self.threads.map(|t| t.to_string())),
+            ("memory_limit", self.memory_limit.clone()),
+            ("temp_directory", self.temp_directory.clone()),
+            (
+                "max_temp_directory_size",
+                self.max_temp_directory_size.clone(),
+            ),
+            (
+                "default_order",
+                self.default_order.map(|o| o.as_setting().to_string()),
+            ),
+            (
+                "default_null_order",
+                self.default_null_order.map(|o| o.as_setting().to_string()),
+            ),
+            (
+                "enable_external_access",
+                self.enable_external_access
+                    .map(|e| bool_setting(e).to_string()),
+            ),
+            (
+                "allow_unsigned_extensions",
+                self.allow_unsigned_extensions
+                    .map(|a| bool_setting(a).to_string()),
+            ),
+        ];
+
+        settings
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect()
+    }
+
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.access_mode == Some(AccessMode::ReadOnly)
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.query_timeout
+    }
+
+    /// Open the connection
+    pub fn establish(&self) -> ConnectionResult<DuckDbConnection> {
+        DuckDbConnection::establish_with_builder(self)
+    }
+}
+
+fn invalid_parameter(key: &str, value: &str) -> ConnectionError {
+    ConnectionError::InvalidConnectionUrl(format!(
+        "Invalid value for DuckDB connection parameter {}: {}",
+        key, value
+    ))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Decode `%XX` escapes in a URL query value
+fn percent_decode(value: &str) -> ConnectionResult<String> {
+    let invalid =
+        || ConnectionError::InvalidConnectionUrl(format!("Invalid percent-encoding: {}", value));
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
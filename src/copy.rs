@@ -0,0 +1,221 @@
+//! `COPY` between files and tables or queries
+
+use diesel::connection::Connection;
+use diesel::query_builder::{AsQuery, AstPass, QueryFragment, QueryId};
+use diesel::{QueryResult, Table};
+
+use crate::attach::{quote_identifier, quote_literal};
+use crate::{DuckDb, DuckDbConnection};
+
+/// File format of [`DuckDbConnection::copy_to`] and [`DuckDbConnection::copy_from`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    Csv,
+    /// Needs the `parquet` feature
+    Parquet,
+    /// Newline delimited JSON, needs the `json` feature
+    Json,
+}
+
+impl CopyFormat {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Compression codec of copied files
+///
+/// CSV and JSON files support `Gzip` and `Zstd`, Parquet files all codecs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyCompression {
+    Uncompressed,
+    Gzip,
+    Zstd,
+    Snappy,
+    Lz4,
+    Brotli,
+}
+
+impl CopyCompression {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Uncompressed => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Snappy => "snappy",
+            Self::Lz4 => "lz4",
+            Self::Brotli => "brotli",
+        }
+    }
+}
+
+/// Options of [`DuckDbConnection::copy_to`] and [`DuckDbConnection::copy_from`]
+///
+/// Unset options keep DuckDB's defaults. DuckDB rejects options that don't
+/// apply to the format or direction, e.g. a delimiter for Parquet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CopyOptions {
+    header: Option<bool>,
+    delimiter: Option<char>,
+    compression: Option<CopyCompression>,
+    partition_by: Vec<String>,
+    row_group_size: Option<u64>,
+}
+
+impl CopyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether CSV files start with a header row
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = Some(header);
+        self
+    }
+
+    /// Column separator of CSV files
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = Some(delimiter);
+        self
+    }
+
+    pub fn compression(mut self, compression: CopyCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Write one directory per value of `columns` (Hive partitioning)
+    ///
+    /// The path given to [`DuckDbConnection::copy_to`] is then a directory.
+    pub fn partition_by<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.partition_by = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Rows per row group of Parquet files
+    pub fn row_group_size(mut self, rows: u64) -> Self {
+        self.row_group_size = Some(rows);
+        self
+    }
+
+    fn to_sql(&self, format: CopyFormat) -> String {
+        let mut options = vec![format!("FORMAT {}", format.name())];
+        if let Some(header) = self.header {
+            options.push(format!("HEADER {}", header));
+        }
+        if let Some(delimiter) = self.delimiter {
+            options.push(format!(
+                "DELIMITER {}",
+                quote_literal(&delimiter.to_string())
+            ));
+        }
+        if let Some(compression) = self.compression {
+            options.push(format!("COMPRESSION {}", compression.name()));
+        }
+        if !self.partition_by.is_empty() {
+            let columns = self
+                .partition_by
+                .iter()
+                .map(|column| quote_identifier(column))
+                .collect::<Vec<_>>();
+            options.push(format!("PARTITION_BY ({})", columns.join(", ")));
+        }
+        if let Some(rows) = self.row_group_size {
+            options.push(format!("ROW_GROUP_SIZE {}", rows));
+        }
+        format!(" ({})", options.join(", "))
+    }
+}
+
+impl DuckDbConnection {
+    /// Write the rows of `query` to the file at `path`, returns the number of rows
+    ///
+    /// `query` is any diesel query, e.g. `users::table.filter(users::age.gt(30))`.
+    /// Its bind parameters are bound to the prepared `COPY` statement, not
+    /// inlined into the SQL.
+    pub fn copy_to<T>(
+        &mut self,
+        query: T,
+        path: &str,
+        format: CopyFormat,
+        options: &CopyOptions,
+    ) -> QueryResult<usize>
+    where
+        T: AsQuery,
+        T::Query: QueryFragment<DuckDb>,
+    {
+        self.execute_returning_count(&CopyStatement {
+            source: CopySource::Query(&query.as_query()),
+            path,
+            options: options.to_sql(format),
+        })
+    }
+
+    /// Append the rows of the file at `path` to `table`, returns the number of rows
+    ///
+    /// The columns of the file are matched to the columns of the table by position.
+    pub fn copy_from<T>(
+        &mut self,
+        table: T,
+        path: &str,
+        format: CopyFormat,
+        options: &CopyOptions,
+    ) -> QueryResult<usize>
+    where
+        T: Table + QueryFragment<DuckDb>,
+    {
+        self.execute_returning_count(&CopyStatement {
+            source: CopySource::Table(&table),
+            path,
+            options: options.to_sql(format),
+        })
+    }
+}
+
+enum CopySource<'a, T> {
+    Query(&'a T),
+    Table(&'a T),
+}
+
+// `COPY (query) TO 'path' (options)` or `COPY table FROM 'path' (options)`
+struct CopyStatement<'a, T> {
+    source: CopySource<'a, T>,
+    path: &'a str,
+    options: String,
+}
+
+impl<T> QueryId for CopyStatement<'_, T> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<T: QueryFragment<DuckDb>> QueryFragment<DuckDb> for CopyStatement<'_, T> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DuckDb>) -> QueryResult<()> {
+        // a statement per file is not worth keeping
+        out.unsafe_to_cache_prepared();
+        out.push_sql("COPY ");
+        match self.source {
+            CopySource::Query(query) => {
+                out.push_sql("(");
+                query.walk_ast(out.reborrow())?;
+                out.push_sql(") TO ");
+            }
+            CopySource::Table(table) => {
+                table.walk_ast(out.reborrow())?;
+                out.push_sql(" FROM ");
+            }
+        }
+        out.push_sql(&quote_literal(self.path));
+        out.push_sql(&self.options);
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+// The raw DuckDB database behind connections opened through a builder
+//
+// duckdb-rs keeps the handle of the databases it opens to itself, but
+// registering aggregate functions needs it, see `aggregate_function`. So the
+// database is opened here with `duckdb_open_ext` and connected to with
+// `Connection::open_from_raw`, which leaves closing it to the `Database`. The
+// connections made by `try_clone` share it, so it is closed once the last of
+// them is dropped.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::Arc;
+
+use diesel::{ConnectionError, ConnectionResult};
+use duckdb::ffi;
+use duckdb::Connection as DuckDBConn;
+
+/// An open database, closed when dropped
+pub(crate) struct Database(ffi::duckdb_database);
+
+// DuckDB databases may be used from any thread
+unsafe impl Send for Database {}
+unsafe impl Sync for Database {}
+
+impl Database {
+    /// The handle of the database, valid as long as `self` is
+    #[cfg(feature = "vscalar")]
+    pub(crate) fn handle(&self) -> ffi::duckdb_database {
+        self.0
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        unsafe { ffi::duckdb_close(&mut self.0) };
+    }
+}
+
+/// Open the database at `path` with the DuckDB `settings`, and a connection
+/// to it that must be dropped before the database
+pub(crate) fn open(
+    path: &str,
+    settings: &[(&str, String)],
+) -> ConnectionResult<(DuckDBConn, Arc<Database>)> {
+    let bad_connection = ConnectionError::BadConnection;
+    let path = CString::new(path).map_err(|e| bad_connection(e.to_string()))?;
+
+    let config = Config::new()?;
+    // set by duckdb-rs for the databases it opens, too
+    for (key, value) in [("duckdb_api", "rust")]
+        .into_iter()
+        .chain(settings.iter().map(|(key, value)| (*key, value.as_str())))
+    {
+        let c_key = CString::new(key).map_err(|e| bad_connection(e.to_string()))?;
+        let c_value = CString::new(value).map_err(|e| bad_connection(e.to_string()))?;
+        let state = unsafe { ffi::duckdb_set_config(config.0, c_key.as_ptr(), c_value.as_ptr()) };
+        if state != ffi::DuckDBSuccess {
+            return Err(bad_connection(format!("set {}:{} error", key, value)));
+        }
+    }
+
+    let mut handle: ffi::duckdb_database = ptr::null_mut();
+    let mut error = ptr::null_mut();
+    let state = unsafe { ffi::duckdb_open_ext(path.as_ptr(), &mut handle, config.0, &mut error) };
+    if state != ffi::DuckDBSuccess {
+        if error.is_null() {
+            return Err(bad_connection("Cannot open the database".to_string()));
+        }
+        let message = unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { ffi::duckdb_free(error.cast()) };
+        return Err(bad_connection(message));
+    }
+    let database = Arc::new(Database(handle));
+
+    let connection = unsafe { DuckDBConn::open_from_raw(database.0) }
+        .map_err(|e| bad_connection(e.to_string()))?;
+    Ok((connection, database))
+}
+
+// Configuration of a database being opened, destroyed when dropped
+struct Config(ffi::duckdb_config);
+
+impl Config {
+    fn new() -> ConnectionResult<Self> {
+        let mut config: ffi::duckdb_config = ptr::null_mut();
+        if unsafe { ffi::duckdb_create_config(&mut config) } != ffi::DuckDBSuccess {
+            return Err(ConnectionError::BadConnection(
+                "Cannot create the database configuration".to_string(),
+            ));
+        }
+        Ok(Self(config))
+    }
+}
+
+impl Drop for Config {
+    fn drop(&mut self) {
+        unsafe { ffi::duckdb_destroy_config(&mut self.0) };
+    }
+}
@@ -0,0 +1,61 @@
+//! Connection pooling with deadpool
+
+use deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
+use diesel::result::ConnectionResult;
+
+pub use crate::pool::PoolError;
+use crate::pool::{recycle, ConnectionFactory};
+use crate::{AsyncDuckDbConnection, DuckDbConnectionBuilder};
+
+pub type Pool = managed::Pool<DuckDbConnectionManager>;
+
+/// deadpool manager for [`AsyncDuckDbConnection`]s
+///
+/// Recycling rolls back transactions left open and checks the connection
+/// with `SELECT 1`.
+#[derive(Debug)]
+pub struct DuckDbConnectionManager {
+    factory: ConnectionFactory,
+}
+
+impl DuckDbConnectionManager {
+    /// Open every pooled connection from `builder`
+    pub fn new(builder: DuckDbConnectionBuilder) -> Self {
+        Self {
+            factory: ConnectionFactory::new(builder),
+        }
+    }
+
+    /// Open the database once and hand out clones of that connection, see
+    /// [`AsyncDuckDbConnection::try_clone`]
+    pub fn shared(builder: DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        Ok(Self {
+            factory: ConnectionFactory::shared(builder)?,
+        })
+    }
+
+    /// SQL run on every new connection, e.g. `SET threads = 4` or `LOAD httpfs`
+    pub fn with_init_sql(mut self, sql: impl Into<String>) -> Self {
+        self.factory.add_init_sql(sql.into());
+        self
+    }
+}
+
+impl managed::Manager for DuckDbConnectionManager {
+    type Type = AsyncDuckDbConnection;
+    type Error = PoolError;
+
+    async fn create(&self) -> Result<AsyncDuckDbConnection, PoolError> {
+        self.factory.connect().await
+    }
+
+    async fn recycle(
+        &self,
+        connection: &mut AsyncDuckDbConnection,
+        _: &Metrics,
+    ) -> RecycleResult<PoolError> {
+        recycle(connection)
+            .await
+            .map_err(|e| RecycleError::Backend(PoolError::QueryError(e)))
+    }
+}
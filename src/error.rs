@@ -1,10 +1,152 @@
 use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
+use unicode_width::UnicodeWidthChar;
 
 /// Trait for converting DuckDB errors to Diesel errors
 pub trait MapDieselError<T> {
     fn map_diesel_error(self) -> diesel::QueryResult<T>;
 }
 
+/// DuckDB-specific checks on diesel errors
+pub trait DuckDbErrorExt {
+    /// Whether the query was aborted by an [`InterruptHandle`](crate::InterruptHandle)
+    /// or its query timeout, with the kind `Interrupt` or `Timeout`
+    fn is_interrupted(&self) -> bool;
+
+    /// Whether the query was aborted because it ran longer than the query
+    /// timeout, with the kind `Timeout`
+    fn is_timeout(&self) -> bool;
+
+    /// The class of the error reported by DuckDB, `None` for errors that
+    /// don't come from the database
+    fn duckdb_error_kind(&self) -> Option<DuckDbErrorKind>;
+}
+
+impl DuckDbErrorExt for DieselError {
+    fn is_interrupted(&self) -> bool {
+        matches!(
+            self.duckdb_error_kind(),
+            Some(DuckDbErrorKind::Interrupt | DuckDbErrorKind::Timeout)
+        )
+    }
+
+    fn is_timeout(&self) -> bool {
+        self.duckdb_error_kind() == Some(DuckDbErrorKind::Timeout)
+    }
+
+    fn duckdb_error_kind(&self) -> Option<DuckDbErrorKind> {
+        match self {
+            DieselError::DatabaseError(_, info) if crate::interrupt::is_timed_out(&**info) => {
+                Some(DuckDbErrorKind::Timeout)
+            }
+            DieselError::DatabaseError(_, info) => {
+                Some(DuckDbErrorKind::from_message(info.message()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Class of a DuckDB error, taken from the `<Class> Error:` prefix of its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DuckDbErrorKind {
+    Binder,
+    Catalog,
+    Connection,
+    Constraint,
+    Conversion,
+    Dependency,
+    Internal,
+    Interrupt,
+    InvalidInput,
+    Io,
+    NotImplemented,
+    OutOfMemory,
+    OutOfRange,
+    Parser,
+    Permission,
+    Serialization,
+    /// A query interrupted by the connection's query timeout; DuckDB reports
+    /// it as an `INTERRUPT`, so only the error returned by the query has this
+    /// kind, never a message passed to [`from_message`](Self::from_message)
+    Timeout,
+    Transaction,
+    /// Any other class, or a message without a class prefix
+    Other,
+}
+
+impl DuckDbErrorKind {
+    // Message prefixes as written by DuckDB, without the trailing ` Error:`
+    const PREFIXES: &'static [(&'static str, DuckDbErrorKind)] = &[
+        ("Binder", DuckDbErrorKind::Binder),
+        ("Catalog", DuckDbErrorKind::Catalog),
+        ("Connection", DuckDbErrorKind::Connection),
+        ("Constraint", DuckDbErrorKind::Constraint),
+        ("Conversion", DuckDbErrorKind::Conversion),
+        ("Dependency", DuckDbErrorKind::Dependency),
+        ("INTERNAL", DuckDbErrorKind::Internal),
+        ("INTERRUPT", DuckDbErrorKind::Interrupt),
+        ("Invalid Input", DuckDbErrorKind::InvalidInput),
+        ("IO", DuckDbErrorKind::Io),
+        ("Not implemented", DuckDbErrorKind::NotImplemented),
+        ("Out of Memory", DuckDbErrorKind::OutOfMemory),
+        ("Out of Range", DuckDbErrorKind::OutOfRange),
+        ("Parser", DuckDbErrorKind::Parser),
+        ("Permission", DuckDbErrorKind::Permission),
+        ("Serialization", DuckDbErrorKind::Serialization),
+        ("TransactionContext", DuckDbErrorKind::Transaction),
+    ];
+
+    /// Classify a DuckDB error message
+    pub fn from_message(message: &str) -> Self {
+        message
+            .split_once(" Error:")
+            .and_then(|(prefix, _)| {
+                Self::PREFIXES
+                    .iter()
+                    .find(|(name, _)| *name == prefix)
+                    .map(|(_, kind)| *kind)
+            })
+            .unwrap_or(DuckDbErrorKind::Other)
+    }
+
+    /// The diesel error kind for an error of this class with `message`
+    fn database_error_kind(self, message: &str) -> DatabaseErrorKind {
+        match self {
+            DuckDbErrorKind::Constraint => {
+                if message.contains("NOT NULL constraint") {
+                    DatabaseErrorKind::NotNullViolation
+                } else if message.contains("CHECK constraint") {
+                    DatabaseErrorKind::CheckViolation
+                } else if message.contains("foreign key") {
+                    DatabaseErrorKind::ForeignKeyViolation
+                } else if message.contains("Duplicate key")
+                    || message.contains("duplicate key")
+                    || message.contains("unique constraint")
+                    || message.contains("primary key constraint")
+                {
+                    DatabaseErrorKind::UniqueViolation
+                } else {
+                    DatabaseErrorKind::Unknown
+                }
+            }
+            // DuckDB's optimistic concurrency control reports write-write
+            // conflicts between transactions as transaction errors
+            DuckDbErrorKind::Transaction if message.to_lowercase().contains("conflict") => {
+                DatabaseErrorKind::SerializationFailure
+            }
+            DuckDbErrorKind::Transaction if message.contains("duplicate key") => {
+                DatabaseErrorKind::UniqueViolation
+            }
+            DuckDbErrorKind::InvalidInput if message.contains("read-only mode") => {
+                DatabaseErrorKind::ReadOnlyTransaction
+            }
+            DuckDbErrorKind::Connection => DatabaseErrorKind::ClosedConnection,
+            _ => DatabaseErrorKind::Unknown,
+        }
+    }
+}
+
 /// DuckDB-specific database error information
 #[derive(Debug)]
 pub struct DuckDbErrorInformation {
@@ -13,6 +155,28 @@ pub struct DuckDbErrorInformation {
     pub column_name: Option<String>,
     pub constraint_name: Option<String>,
     pub statement_position: Option<i32>,
+    pub details: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl DuckDbErrorInformation {
+    // Information for an error raised by this crate rather than by DuckDB
+    pub(crate) fn from_message(message: String) -> Self {
+        Self {
+            error_message: message,
+            table_name: None,
+            column_name: None,
+            constraint_name: None,
+            statement_position: None,
+            details: None,
+            hint: None,
+        }
+    }
+
+    /// The class of the error, taken from the prefix of its message
+    pub fn kind(&self) -> DuckDbErrorKind {
+        DuckDbErrorKind::from_message(&self.error_message)
+    }
 }
 
 impl DatabaseErrorInformation for DuckDbErrorInformation {
@@ -21,11 +185,11 @@ impl DatabaseErrorInformation for DuckDbErrorInformation {
     }
 
     fn details(&self) -> Option<&str> {
-        None
+        self.details.as_deref()
     }
 
     fn hint(&self) -> Option<&str> {
-        None
+        self.hint.as_deref()
     }
 
     fn table_name(&self) -> Option<&str> {
@@ -47,117 +211,215 @@ impl DatabaseErrorInformation for DuckDbErrorInformation {
 
 impl<T> MapDieselError<T> for Result<T, duckdb::Error> {
     fn map_diesel_error(self) -> diesel::QueryResult<T> {
-        self.map_err(|e| {
-            use duckdb::Error;
-
-            match e {
-                // DuckDB-specific failures with error codes and optional messages
-                Error::DuckDBFailure(error_code, message_opt) => {
-                    let message =
-                        message_opt.unwrap_or_else(|| format!("DuckDB error code: {}", error_code));
-                    let error_info = DuckDbErrorInformation {
-                        error_message: message.clone(),
-                        table_name: extract_table_name(&message),
-                        column_name: extract_column_name(&message),
-                        constraint_name: extract_constraint_name(&message),
-                        statement_position: None,
-                    };
-
-                    // Map based on error message content since DuckDB error codes vary
-                    let message_lower = message.to_lowercase();
-                    let kind = if message_lower.contains("unique")
-                        || message_lower.contains("duplicate")
-                    {
-                        DatabaseErrorKind::UniqueViolation
-                    } else if message_lower.contains("not null") {
-                        DatabaseErrorKind::NotNullViolation
-                    } else if message_lower.contains("foreign key") {
-                        DatabaseErrorKind::ForeignKeyViolation
-                    } else if message_lower.contains("check constraint") {
-                        DatabaseErrorKind::CheckViolation
-                    } else {
-                        DatabaseErrorKind::Unknown
-                    };
-
-                    DieselError::DatabaseError(kind, Box::new(error_info))
-                }
+        map_error(self, None)
+    }
+}
 
-                // Column access errors
-                Error::InvalidColumnIndex(index) => DieselError::DeserializationError(
-                    format!("Invalid column index: {}", index).into(),
-                ),
-
-                Error::InvalidColumnName(name) => DieselError::DeserializationError(
-                    format!("Invalid column name: {}", name).into(),
-                ),
-
-                Error::InvalidColumnType(index, name, type_name) => {
-                    DieselError::DeserializationError(
-                        format!(
-                            "Invalid column type at index {}, name '{}': {}",
-                            index, name, type_name
-                        )
-                        .into(),
-                    )
-                }
+/// Like [`MapDieselError`], for errors of running `sql` so they can point into it
+pub(crate) trait MapQueryError<T> {
+    fn map_query_error(self, sql: &str) -> diesel::QueryResult<T>;
+}
 
-                // Parameter binding errors
-                Error::InvalidParameterCount(expected, actual) => {
-                    let error_info = DuckDbErrorInformation {
-                        error_message: format!(
-                            "Invalid parameter count: expected {}, got {}",
-                            expected, actual
-                        ),
-                        table_name: None,
-                        column_name: None,
-                        constraint_name: None,
-                        statement_position: None,
-                    };
-                    DieselError::DatabaseError(DatabaseErrorKind::Unknown, Box::new(error_info))
-                }
+impl<T> MapQueryError<T> for Result<T, duckdb::Error> {
+    fn map_query_error(self, sql: &str) -> diesel::QueryResult<T> {
+        map_error(self, Some(sql))
+    }
+}
 
-                Error::StatementChangedRows(count) => DieselError::DeserializationError(
-                    format!("Unexpected number of changed rows: {}", count).into(),
-                ),
+fn map_error<T>(result: Result<T, duckdb::Error>, sql: Option<&str>) -> diesel::QueryResult<T> {
+    result.map_err(|e| {
+        use duckdb::Error;
 
-                // Path and file system errors
-                Error::InvalidPath(path) => DieselError::DeserializationError(
-                    format!("Invalid path: {}", path.display()).into(),
-                ),
+        match e {
+            // DuckDB-specific failures with error codes and optional messages
+            Error::DuckDBFailure(error_code, message_opt) => {
+                let message =
+                    message_opt.unwrap_or_else(|| format!("DuckDB error code: {}", error_code));
+                // DuckDB reports almost every failure with the same error code,
+                // the class is in the message prefix instead
+                let kind = DuckDbErrorKind::from_message(&message).database_error_kind(&message);
+                let error_info = DuckDbErrorInformation {
+                    table_name: extract_table_name(&message),
+                    column_name: extract_column_name(&message),
+                    constraint_name: extract_constraint_name(&message),
+                    statement_position: statement_position(&message, sql),
+                    details: error_details(&message),
+                    hint: error_hint(&message),
+                    error_message: message,
+                };
 
-                // Conversion errors
-                Error::ToSqlConversionFailure(err) => DieselError::SerializationError(err),
+                DieselError::DatabaseError(kind, Box::new(error_info))
+            }
+
+            // Column access errors
+            Error::InvalidColumnIndex(index) => DieselError::DeserializationError(
+                format!("Invalid column index: {}", index).into(),
+            ),
+
+            Error::InvalidColumnName(name) => DieselError::DeserializationError(
+                format!("Invalid column name: {}", name).into(),
+            ),
 
-                Error::FromSqlConversionFailure(idx, name, err) => {
-                    DieselError::DeserializationError(
-                        format!("Conversion failure at column {} ('{}'): {}", idx, name, err)
-                            .into(),
+            Error::InvalidColumnType(index, name, type_name) => {
+                DieselError::DeserializationError(
+                    format!(
+                        "Invalid column type at index {}, name '{}': {}",
+                        index, name, type_name
                     )
-                }
+                    .into(),
+                )
+            }
 
-                // String encoding errors
-                Error::Utf8Error(err) => DieselError::DeserializationError(
-                    format!("UTF-8 conversion error: {}", err).into(),
-                ),
-
-                Error::NulError(err) => DieselError::DeserializationError(
-                    format!("Null byte in string: {}", err).into(),
-                ),
-
-                // Catch-all for any other error variants
-                _ => {
-                    let error_info = DuckDbErrorInformation {
-                        error_message: e.to_string(),
-                        table_name: None,
-                        column_name: None,
-                        constraint_name: None,
-                        statement_position: None,
-                    };
-                    DieselError::DatabaseError(DatabaseErrorKind::Unknown, Box::new(error_info))
-                }
+            // Parameter binding errors
+            Error::InvalidParameterCount(expected, actual) => {
+                let error_info = DuckDbErrorInformation {
+                    error_message: format!(
+                        "Invalid parameter count: expected {}, got {}",
+                        expected, actual
+                    ),
+                    table_name: None,
+                    column_name: None,
+                    constraint_name: None,
+                    statement_position: None,
+                    details: None,
+                    hint: None,
+                };
+                DieselError::DatabaseError(DatabaseErrorKind::Unknown, Box::new(error_info))
+            }
+
+            Error::StatementChangedRows(count) => DieselError::DeserializationError(
+                format!("Unexpected number of changed rows: {}", count).into(),
+            ),
+
+            // Path and file system errors
+            Error::InvalidPath(path) => DieselError::DeserializationError(
+                format!("Invalid path: {}", path.display()).into(),
+            ),
+
+            // Conversion errors
+            Error::ToSqlConversionFailure(err) => DieselError::SerializationError(err),
+
+            Error::FromSqlConversionFailure(idx, name, err) => {
+                DieselError::DeserializationError(
+                    format!("Conversion failure at column {} ('{}'): {}", idx, name, err)
+                        .into(),
+                )
+            }
+
+            // String encoding errors
+            Error::Utf8Error(err) => DieselError::DeserializationError(
+                format!("UTF-8 conversion error: {}", err).into(),
+            ),
+
+            Error::NulError(err) => DieselError::DeserializationError(
+                format!("Null byte in string: {}", err).into(),
+            ),
+
+            // Catch-all for any other error variants
+            _ => {
+                let error_info = DuckDbErrorInformation {
+                    error_message: e.to_string(),
+                    table_name: None,
+                    column_name: None,
+                    constraint_name: None,
+                    statement_position: None,
+                    details: None,
+                    hint: None,
+                };
+                DieselError::DatabaseError(DatabaseErrorKind::Unknown, Box::new(error_info))
             }
-        })
+        }
+    })
+}
+
+/// Split a DuckDB error message into its first line, the extra lines
+/// explaining it and the context pointing at the error in the statement
+///
+/// The context is separated by an empty line and looks like
+/// `LINE 2: <line of the statement>` followed by a line with a caret under
+/// the error.
+fn split_message(message: &str) -> (&str, Vec<&str>, Option<&str>) {
+    let (body, context) = match message.split_once("\n\nLINE ") {
+        Some((body, context)) => (body, Some(context)),
+        None => (message, None),
+    };
+    let mut lines = body.lines();
+    let first = lines.next().unwrap_or_default();
+    let extra = lines.map(str::trim).filter(|line| !line.is_empty()).collect();
+    (first, extra, context)
+}
+
+/// Suggestions DuckDB makes for misspelled names
+fn is_hint(line: &str) -> bool {
+    line.starts_with("Did you mean") || line.starts_with("Candidate bindings:")
+}
+
+fn error_hint(message: &str) -> Option<String> {
+    let (_, extra, _) = split_message(message);
+    let hint = extra.into_iter().find(|line| is_hint(line))?;
+    Some(hint.to_string())
+}
+
+/// Extra lines of the message besides the hint, e.g. candidate functions
+fn error_details(message: &str) -> Option<String> {
+    let (_, extra, _) = split_message(message);
+    let details: Vec<_> = extra.into_iter().filter(|line| !is_hint(line)).collect();
+    (!details.is_empty()).then(|| details.join("\n"))
+}
+
+/// 1-based character position of the error in the statement
+///
+/// DuckDB only shows the line of the error, shortened around the error if it
+/// is long, so without the statement itself the position is only known for
+/// errors on a short first line.
+fn statement_position(message: &str, sql: Option<&str>) -> Option<i32> {
+    let (_, _, context) = split_message(message);
+    let (line_number, rest) = context?.split_once(": ")?;
+    let (shown, caret) = rest.split_once('\n')?;
+    let indent = "LINE ".len() + line_number.len() + ": ".len();
+    let caret_column = caret.chars().position(|c| c == '^')?.checked_sub(indent)?;
+    let column = char_at_column(shown, caret_column)?;
+    let lines_before = line_number.parse::<usize>().ok()?.checked_sub(1)?;
+
+    let shortened = shown.strip_prefix("...");
+    let position = match sql {
+        Some(sql) => {
+            let mut lines = sql.split('\n');
+            let before: usize = lines
+                .by_ref()
+                .take(lines_before)
+                .map(|line| line.chars().count() + 1)
+                .sum();
+            let line = lines.next()?;
+            let in_line = match shortened {
+                Some(shown) => {
+                    let shown = shown.strip_suffix("...").unwrap_or(shown);
+                    let start = line.find(shown)?;
+                    line[..start].chars().count() + column.checked_sub("...".len())?
+                }
+                None => column,
+            };
+            before + in_line
+        }
+        None if lines_before == 0 && shortened.is_none() => column,
+        None => return None,
+    };
+    i32::try_from(position + 1).ok()
+}
+
+// Index of the char of `text` shown at display column `column`
+//
+// DuckDB lines the caret up by display width, wide characters such as CJK
+// take two columns.
+fn char_at_column(text: &str, column: usize) -> Option<usize> {
+    let mut width = 0;
+    for (index, c) in text.chars().enumerate() {
+        if width >= column {
+            return Some(index);
+        }
+        width += c.width().unwrap_or(0);
     }
+    (width == column).then(|| text.chars().count())
 }
 
 /// Extract table name from error message using common patterns
@@ -196,3 +458,46 @@ fn extract_constraint_name(message: &str) -> Option<String> {
     }
     None
 }
+
+/// Error returned when a DuckDB value cannot be converted into the requested Rust type
+#[derive(Debug, Clone, PartialEq)]
+pub enum FromDuckDbValueError {
+    /// The value has a DuckDB type that cannot be read as the requested Rust type
+    InvalidType {
+        target: &'static str,
+        found: &'static str,
+    },
+    /// The value has the right type but does not fit into the requested Rust type
+    OutOfRange {
+        target: &'static str,
+        value: String,
+    },
+    /// The value could not be parsed or decoded as the requested Rust type
+    Invalid {
+        target: &'static str,
+        message: String,
+    },
+    /// The raw value handed to diesel uses a representation this backend does not produce
+    UnsupportedRawValue,
+}
+
+impl std::fmt::Display for FromDuckDbValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FromDuckDbValueError::InvalidType { target, found } => {
+                write!(f, "Cannot read DuckDB {} value as {}", found, target)
+            }
+            FromDuckDbValueError::OutOfRange { target, value } => {
+                write!(f, "DuckDB value {} is out of range for {}", value, target)
+            }
+            FromDuckDbValueError::Invalid { target, message } => {
+                write!(f, "Invalid DuckDB value for {}: {}", target, message)
+            }
+            FromDuckDbValueError::UnsupportedRawValue => {
+                write!(f, "Unsupported DuckDB raw value representation")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromDuckDbValueError {}
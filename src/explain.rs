@@ -0,0 +1,180 @@
+//! Query plans from `EXPLAIN` and `EXPLAIN ANALYZE`
+//!
+//! DuckDB renders plans as JSON when asked to, which is parsed into a tree of
+//! [`PlanNode`]s.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Text;
+use diesel::{QueryResult, RunQueryDsl};
+use serde_json::Value;
+
+use crate::{DuckDb, DuckDbConnection};
+
+/// An operator of a query plan
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanNode {
+    /// Operator name, e.g. `SEQ_SCAN` or `HASH_JOIN`
+    pub operator: String,
+    /// Operator details such as the scanned table, filters or join conditions
+    ///
+    /// Details with several values are joined with newlines.
+    pub details: BTreeMap<String, String>,
+    /// Rows the planner expects the operator to produce
+    pub estimated_cardinality: Option<u64>,
+    /// Rows the operator produced, only set by `explain_analyze`
+    pub cardinality: Option<u64>,
+    /// Rows the operator read from storage, only set by `explain_analyze`
+    pub rows_scanned: Option<u64>,
+    /// Time spent in the operator, only set by `explain_analyze`
+    pub timing: Option<Duration>,
+    pub children: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    /// This node and all nodes below it, depth first
+    pub fn nodes(&self) -> impl Iterator<Item = &PlanNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    fn from_json(value: &Value) -> QueryResult<Self> {
+        let operator = value
+            .get("operator_name")
+            .or_else(|| value.get("name"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| plan_error("plan node without an operator name"))?;
+
+        let mut details = BTreeMap::new();
+        if let Some(extra_info) = value.get("extra_info").and_then(Value::as_object) {
+            for (key, value) in extra_info {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Array(values) => values
+                        .iter()
+                        .map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_string))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    other => other.to_string(),
+                };
+                details.insert(key.clone(), value);
+            }
+        }
+        let estimated_cardinality = details
+            .remove("Estimated Cardinality")
+            .and_then(|c| c.trim_start_matches('~').parse().ok());
+
+        let children = value
+            .get("children")
+            .and_then(Value::as_array)
+            .map(|children| children.iter().map(Self::from_json).collect())
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            operator: operator.trim().to_string(),
+            details,
+            estimated_cardinality,
+            cardinality: value.get("operator_cardinality").and_then(Value::as_u64),
+            rows_scanned: value.get("operator_rows_scanned").and_then(Value::as_u64),
+            timing: value
+                .get("operator_timing")
+                .and_then(Value::as_f64)
+                .map(Duration::from_secs_f64),
+            children,
+        })
+    }
+}
+
+impl DuckDbConnection {
+    /// The plan DuckDB would use for `query`, without running it
+    pub fn explain<T>(&mut self, query: &T) -> QueryResult<PlanNode>
+    where
+        T: QueryFragment<DuckDb> + QueryId,
+    {
+        let plan = self.explain_json(query, false)?;
+        match plan.as_array().and_then(|roots| roots.first()) {
+            Some(root) => PlanNode::from_json(root),
+            None => Err(plan_error("empty query plan")),
+        }
+    }
+
+    /// Run `query` and return its plan with the rows and time of every operator
+    ///
+    /// The query is really executed, so an `INSERT`, `UPDATE` or `DELETE`
+    /// modifies the database.
+    pub fn explain_analyze<T>(&mut self, query: &T) -> QueryResult<PlanNode>
+    where
+        T: QueryFragment<DuckDb> + QueryId,
+    {
+        let profile = self.explain_json(query, true)?;
+        let root = profile
+            .get("children")
+            .and_then(Value::as_array)
+            .and_then(|children| children.first())
+            .ok_or_else(|| plan_error("empty query profile"))?;
+
+        let root = PlanNode::from_json(root)?;
+        // the profile is rooted at the EXPLAIN ANALYZE operator itself
+        match root.operator.as_str() {
+            "EXPLAIN_ANALYZE" => root
+                .children
+                .into_iter()
+                .next()
+                .ok_or_else(|| plan_error("empty query profile")),
+            _ => Ok(root),
+        }
+    }
+
+    fn explain_json<T>(&mut self, query: &T, analyze: bool) -> QueryResult<Value>
+    where
+        T: QueryFragment<DuckDb> + QueryId,
+    {
+        let rows = Explain { query, analyze }.load::<(String, String)>(self)?;
+        let (_, plan) = rows
+            .into_iter()
+            .next()
+            .ok_or_else(|| plan_error("EXPLAIN returned no rows"))?;
+        serde_json::from_str(&plan).map_err(|e| DieselError::DeserializationError(e.into()))
+    }
+}
+
+// `EXPLAIN` in front of a query, returning the `(explain_key, explain_value)` rows
+struct Explain<'a, T> {
+    query: &'a T,
+    analyze: bool,
+}
+
+impl<T> QueryId for Explain<'_, T> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<T> Query for Explain<'_, T> {
+    type SqlType = (Text, Text);
+}
+
+impl<T> RunQueryDsl<DuckDbConnection> for Explain<'_, T> {}
+
+impl<T: QueryFragment<DuckDb>> QueryFragment<DuckDb> for Explain<'_, T> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DuckDb>) -> QueryResult<()> {
+        if self.analyze {
+            out.push_sql("EXPLAIN (ANALYZE, FORMAT json) ");
+        } else {
+            out.push_sql("EXPLAIN (FORMAT json) ");
+        }
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+fn plan_error(message: &str) -> DieselError {
+    DieselError::DeserializationError(message.into())
+}
@@ -0,0 +1,171 @@
+//! Backups with `EXPORT DATABASE` and `IMPORT DATABASE`
+
+use std::path::PathBuf;
+
+use diesel::connection::SimpleConnection;
+use diesel::result::Error as DieselError;
+use diesel::QueryResult;
+
+use crate::attach::quote_literal;
+use crate::DuckDbConnection;
+
+/// File format of the table data written by [`DuckDbConnection::export_database`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// CSV files with a header row
+    #[default]
+    Csv,
+    /// Parquet files, needs the `parquet` feature
+    Parquet { compression: ParquetCompression },
+}
+
+/// Compression codec of exported Parquet files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParquetCompression {
+    Uncompressed,
+    #[default]
+    Snappy,
+    Gzip,
+    Zstd,
+    Lz4,
+    Brotli,
+}
+
+impl ParquetCompression {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Uncompressed => "uncompressed",
+            Self::Snappy => "snappy",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Lz4 => "lz4",
+            Self::Brotli => "brotli",
+        }
+    }
+}
+
+impl ExportFormat {
+    fn to_sql(self) -> String {
+        match self {
+            Self::Csv => " (FORMAT csv)".to_string(),
+            Self::Parquet { compression } => {
+                format!(" (FORMAT parquet, COMPRESSION {})", compression.name())
+            }
+        }
+    }
+}
+
+/// What [`DuckDbConnection::export_database`] wrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportManifest {
+    /// The export directory, which also holds `schema.sql` and `load.sql`
+    pub directory: PathBuf,
+    /// The exported tables, in the order `IMPORT DATABASE` loads them
+    pub tables: Vec<ExportedTable>,
+}
+
+/// A table written by [`DuckDbConnection::export_database`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedTable {
+    pub schema: String,
+    pub name: String,
+    /// The file holding the table's rows
+    pub path: PathBuf,
+}
+
+impl DuckDbConnection {
+    /// Write the schema and data of the database to the directory `dir`
+    ///
+    /// The directory is created if needed, files of a previous export in it
+    /// are overwritten. Views, sequences, macros and the like are exported
+    /// with the schema, only tables get a data file.
+    pub fn export_database(
+        &mut self,
+        dir: &str,
+        format: ExportFormat,
+    ) -> QueryResult<ExportManifest> {
+        self.batch_execute(&format!(
+            "EXPORT DATABASE {}{}",
+            quote_literal(dir),
+            format.to_sql()
+        ))?;
+
+        let directory = PathBuf::from(dir);
+        let load = std::fs::read_to_string(directory.join("load.sql"))
+            .map_err(|e| DieselError::DeserializationError(Box::new(e)))?;
+        let tables = load
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                parse_load_statement(line).ok_or_else(|| {
+                    DieselError::DeserializationError(
+                        format!("unexpected statement in load.sql: {}", line).into(),
+                    )
+                })
+            })
+            .collect::<QueryResult<_>>()?;
+
+        Ok(ExportManifest { directory, tables })
+    }
+
+    /// Create the schema and load the data exported to the directory `dir`
+    ///
+    /// The database should be empty, importing fails on the first object
+    /// that already exists.
+    pub fn import_database(&mut self, dir: &str) -> QueryResult<()> {
+        self.batch_execute(&format!("IMPORT DATABASE {}", quote_literal(dir)))
+    }
+}
+
+// A line of load.sql, e.g. `COPY main."my table" FROM 'dir/my_table.csv' (FORMAT 'csv', ...);`
+fn parse_load_statement(line: &str) -> Option<ExportedTable> {
+    let mut rest = line.strip_prefix("COPY ")?;
+
+    let mut parts = Vec::new();
+    loop {
+        let (part, after) = parse_identifier(rest)?;
+        parts.push(part);
+        match after.strip_prefix('.') {
+            Some(after) => rest = after,
+            None => {
+                rest = after;
+                break;
+            }
+        }
+    }
+    let (path, _) = parse_quoted(rest.strip_prefix(" FROM ")?, '\'')?;
+
+    let name = parts.pop()?;
+    let schema = parts.pop().unwrap_or_else(|| "main".to_string());
+    Some(ExportedTable {
+        schema,
+        name,
+        path: PathBuf::from(path),
+    })
+}
+
+fn parse_identifier(input: &str) -> Option<(String, &str)> {
+    if input.starts_with('"') {
+        return parse_quoted(input, '"');
+    }
+    let end = input.find(['.', ' ']).unwrap_or(input.len());
+    (end > 0).then(|| (input[..end].to_string(), &input[end..]))
+}
+
+// Parse a string quoted with `quote`, in which the quote is escaped by doubling it
+fn parse_quoted(input: &str, quote: char) -> Option<(String, &str)> {
+    let mut rest = input.strip_prefix(quote)?;
+    let mut value = String::new();
+    loop {
+        let end = rest.find(quote)?;
+        value.push_str(&rest[..end]);
+        rest = &rest[end + 1..];
+        match rest.strip_prefix(quote) {
+            Some(after) => {
+                value.push(quote);
+                rest = after;
+            }
+            None => return Some((value, rest)),
+        }
+    }
+}
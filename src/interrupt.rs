@@ -0,0 +1,208 @@
+//! Cancelling running queries from another thread or after a timeout
+
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
+use diesel::QueryResult;
+
+use crate::{DuckDbConnection, DuckDbErrorInformation, DuckDbErrorKind};
+
+/// Aborts the query running on a [`DuckDbConnection`]
+///
+/// The handle can be sent to and kept by other threads. The interrupted
+/// query fails with an error of the kind
+/// [`DuckDbErrorKind::Interrupt`](crate::DuckDbErrorKind::Interrupt). A
+/// handle obtained before [`DuckDbConnection::reopen`] does nothing
+/// afterwards.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    handle: Arc<duckdb::InterruptHandle>,
+}
+
+impl InterruptHandle {
+    /// Abort the running query, if there is one
+    pub fn interrupt(&self) {
+        self.handle.interrupt();
+    }
+}
+
+impl DuckDbConnection {
+    /// A handle to abort the running query from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            handle: self.as_ref().interrupt_handle(),
+        }
+    }
+
+    /// Run `f` with the query timeout set to `timeout`
+    ///
+    /// The timeout applies to every query `f` runs on its own, the previous
+    /// timeout is restored afterwards.
+    pub fn with_timeout<R, E>(
+        &mut self,
+        timeout: Duration,
+        f: impl FnOnce(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let previous = self.query_timeout();
+        self.set_query_timeout(Some(timeout));
+        let result = f(self);
+        self.set_query_timeout(previous);
+        result
+    }
+}
+
+// Background thread interrupting queries that run past their deadline
+//
+// The thread is started with the first armed query and stops when the
+// watchdog is dropped.
+pub(crate) struct Watchdog {
+    state: Arc<(Mutex<WatchState>, Condvar)>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    deadline: Option<Instant>,
+    fired: bool,
+    closed: bool,
+}
+
+impl Watchdog {
+    pub(crate) fn new(handle: Arc<duckdb::InterruptHandle>) -> QueryResult<Self> {
+        let state = Arc::new((Mutex::new(WatchState::default()), Condvar::new()));
+        let watched = Arc::clone(&state);
+
+        std::thread::Builder::new()
+            .name("diesel-duckdb-watchdog".to_string())
+            .spawn(move || watch(&watched, &handle))
+            .map_err(|e| {
+                DieselError::DatabaseError(
+                    DatabaseErrorKind::UnableToSendCommand,
+                    Box::new(DuckDbErrorInformation::from_message(format!(
+                        "Connection Error: Failed to start the query timeout thread: {}",
+                        e
+                    ))),
+                )
+            })?;
+
+        Ok(Self { state })
+    }
+
+    /// Interrupt the running query once `timeout` has passed
+    pub(crate) fn arm(&self, timeout: Duration) {
+        let (state, wakeup) = &*self.state;
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.deadline = Some(Instant::now() + timeout);
+        state.fired = false;
+        wakeup.notify_one();
+    }
+
+    /// Stop watching the query, returns whether it was interrupted
+    pub(crate) fn disarm(&self) -> bool {
+        let (state, _) = &*self.state;
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.deadline = None;
+        std::mem::take(&mut state.fired)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let (state, wakeup) = &*self.state;
+        state.lock().unwrap_or_else(PoisonError::into_inner).closed = true;
+        wakeup.notify_one();
+    }
+}
+
+fn watch(state: &(Mutex<WatchState>, Condvar), handle: &duckdb::InterruptHandle) {
+    let (state, wakeup) = state;
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+
+    while !state.closed {
+        state = match state.deadline {
+            None => wakeup.wait(state).unwrap_or_else(PoisonError::into_inner),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    handle.interrupt();
+                    state.deadline = None;
+                    state.fired = true;
+                    continue;
+                }
+                wakeup
+                    .wait_timeout(state, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+        };
+    }
+}
+
+/// Mark the error of a query interrupted by its timeout
+///
+/// The error keeps DuckDB's message, the timeout is told apart from other
+/// interrupts by [`is_timed_out`].
+pub(crate) fn timed_out<R>(result: QueryResult<R>) -> QueryResult<R> {
+    match result {
+        Err(DieselError::DatabaseError(kind, info))
+            if DuckDbErrorKind::from_message(info.message()) == DuckDbErrorKind::Interrupt =>
+        {
+            Err(DieselError::DatabaseError(
+                kind,
+                Box::new(TimedOut { info }),
+            ))
+        }
+        result => result,
+    }
+}
+
+// Details of every error made by `timed_out`
+//
+// `DatabaseErrorInformation` can't be downcast, so a timeout is recognised by
+// the address of its details: a static has an address of its own, which no
+// message or details of DuckDB's can share.
+static TIMED_OUT: [u8; 37] = *b"The query ran longer than its timeout";
+
+fn timed_out_details() -> &'static str {
+    std::str::from_utf8(&TIMED_OUT).unwrap_or_default()
+}
+
+/// Whether the error information was made by [`timed_out`]
+pub(crate) fn is_timed_out(info: &dyn DatabaseErrorInformation) -> bool {
+    info.details()
+        .is_some_and(|details| std::ptr::eq(details, timed_out_details()))
+}
+
+struct TimedOut {
+    info: Box<dyn DatabaseErrorInformation + Send + Sync>,
+}
+
+impl DatabaseErrorInformation for TimedOut {
+    fn message(&self) -> &str {
+        self.info.message()
+    }
+
+    fn details(&self) -> Option<&str> {
+        Some(timed_out_details())
+    }
+
+    fn hint(&self) -> Option<&str> {
+        self.info.hint()
+    }
+
+    fn table_name(&self) -> Option<&str> {
+        self.info.table_name()
+    }
+
+    fn column_name(&self) -> Option<&str> {
+        self.info.column_name()
+    }
+
+    fn constraint_name(&self) -> Option<&str> {
+        self.info.constraint_name()
+    }
+
+    fn statement_position(&self) -> Option<i32> {
+        self.info.statement_position()
+    }
+}
@@ -1,15 +1,79 @@
+//! Diesel backend for DuckDB
+//!
+//! # Limitations
+//!
+//! There is no progress callback for running queries. DuckDB reports
+//! progress through `duckdb_query_progress`, which needs the raw handle of
+//! the connection running the query, and duckdb-rs doesn't expose that
+//! handle. To stop a long query, use
+//! [`DuckDbConnection::interrupt_handle`] from another thread or
+//! [`DuckDbConnection::set_query_timeout`].
+
+#[cfg(feature = "vscalar")]
+pub mod aggregate_function;
+#[cfg(feature = "async")]
+pub mod async_connection;
+pub mod attach;
 pub mod backend;
+#[cfg(feature = "bb8")]
+pub mod bb8;
 mod bind_collector;
 pub mod connection;
+pub mod connection_builder;
+pub mod copy;
+mod database;
+#[cfg(feature = "deadpool")]
+pub mod deadpool;
 pub mod error;
+pub mod explain;
+pub mod export;
+pub mod interrupt;
+pub mod maintenance;
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+mod pool;
 mod query_builder;
 mod query_fragments;
+#[cfg(feature = "r2d2")]
+pub mod r2d2;
+#[cfg(feature = "vtab")]
+mod registry;
+pub mod retry;
+#[cfg(feature = "vscalar")]
+pub mod scalar_function;
+pub mod table_function;
+pub mod table_source;
+pub mod transaction_manager;
+mod tracing_support;
 pub mod types;
+mod value;
+#[cfg(feature = "vtab")]
+pub mod virtual_table;
 mod chrono_support;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "vscalar")]
+pub use aggregate_function::AggregateFunction;
+#[cfg(feature = "async")]
+pub use async_connection::AsyncDuckDbConnection;
+pub use attach::{AttachOptions, AttachedCatalog, CatalogTable, InCatalog};
 pub use backend::DuckDb;
 pub use connection::DuckDbConnection;
-pub use error::{DuckDbErrorInformation, MapDieselError};
+pub use connection_builder::{AccessMode, DefaultOrder, DuckDbConnectionBuilder, NullOrder};
+pub use copy::{CopyCompression, CopyFormat, CopyOptions};
+pub use error::{
+    DuckDbErrorExt, DuckDbErrorInformation, DuckDbErrorKind, FromDuckDbValueError, MapDieselError,
+};
+pub use explain::PlanNode;
+pub use export::{ExportFormat, ExportManifest, ExportedTable, ParquetCompression};
+pub use interrupt::InterruptHandle;
+pub use maintenance::DatabaseSize;
+pub use retry::RetryPolicy;
+#[cfg(feature = "vscalar")]
+pub use scalar_function::{ScalarArgs, ScalarFunctionError, ScalarType};
+pub use table_function::{FromTableFunction, TableFunction, TableFunctionSource, TableFunctionValue};
+pub use table_source::{FromClauseSource, TableSource};
+pub use transaction_manager::DuckDbTransactionManager;
+#[cfg(feature = "vtab")]
+pub use virtual_table::{VirtualColumnType, VirtualTable};
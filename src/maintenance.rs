@@ -0,0 +1,123 @@
+//! Checkpoints, vacuuming and storage statistics
+
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::{QueryResult, QueryableByName, RunQueryDsl};
+
+use crate::attach::quote_identifier;
+use crate::DuckDbConnection;
+
+/// Storage used by an attached database, from `pragma_database_size`
+///
+/// Sizes DuckDB only reports formatted, e.g. `"1.5 MiB"`, are kept as such.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseSize {
+    pub database_name: String,
+    /// Size of the database, e.g. `"1.5 MiB"`
+    pub database_size: String,
+    /// Size of a storage block in bytes
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub used_blocks: u64,
+    pub free_blocks: u64,
+    /// Size of the write-ahead log, `None` for in-memory databases
+    pub wal_size: Option<String>,
+    /// Memory used by the whole DuckDB instance
+    pub memory_usage: String,
+    /// Memory limit of the whole DuckDB instance, `"Unlimited"` if there is none
+    pub memory_limit: String,
+}
+
+impl DatabaseSize {
+    /// Bytes taken by the blocks of the database, used or free
+    pub fn bytes(&self) -> u64 {
+        self.block_size * self.total_blocks
+    }
+}
+
+#[derive(QueryableByName)]
+struct DatabaseSizeRow {
+    #[diesel(sql_type = Text)]
+    database_name: String,
+    #[diesel(sql_type = Text)]
+    database_size: String,
+    #[diesel(sql_type = BigInt)]
+    block_size: i64,
+    #[diesel(sql_type = BigInt)]
+    total_blocks: i64,
+    #[diesel(sql_type = BigInt)]
+    used_blocks: i64,
+    #[diesel(sql_type = BigInt)]
+    free_blocks: i64,
+    #[diesel(sql_type = Nullable<Text>)]
+    wal_size: Option<String>,
+    #[diesel(sql_type = Text)]
+    memory_usage: String,
+    #[diesel(sql_type = Text)]
+    memory_limit: String,
+}
+
+impl From<DatabaseSizeRow> for DatabaseSize {
+    fn from(row: DatabaseSizeRow) -> Self {
+        // DuckDB reports these as BIGINT but they are never negative
+        let unsigned = |value: i64| u64::try_from(value).unwrap_or_default();
+        Self {
+            database_name: row.database_name,
+            database_size: row.database_size,
+            block_size: unsigned(row.block_size),
+            total_blocks: unsigned(row.total_blocks),
+            used_blocks: unsigned(row.used_blocks),
+            free_blocks: unsigned(row.free_blocks),
+            wal_size: row.wal_size,
+            memory_usage: row.memory_usage,
+            memory_limit: row.memory_limit,
+        }
+    }
+}
+
+impl DuckDbConnection {
+    /// Write the write-ahead log into the database file
+    ///
+    /// Does nothing while other transactions are running, see
+    /// [`force_checkpoint`](Self::force_checkpoint).
+    pub fn checkpoint(&mut self) -> QueryResult<()> {
+        self.batch_execute("CHECKPOINT")
+    }
+
+    /// Write the write-ahead log into the database file, aborting the
+    /// transactions of other connections to the database
+    pub fn force_checkpoint(&mut self) -> QueryResult<()> {
+        self.batch_execute("FORCE CHECKPOINT")
+    }
+
+    /// Vacuum `table`, in `schema` if given
+    ///
+    /// DuckDB reclaims the space of deleted rows during checkpoints, so this
+    /// mostly exists for compatibility. `schema` may also name an attached
+    /// database. Both names are quoted as they are, dots included.
+    pub fn vacuum(&mut self, schema: Option<&str>, table: &str) -> QueryResult<()> {
+        self.batch_execute(&format!("VACUUM {}", qualified_name(schema, table)))
+    }
+
+    /// Recompute the statistics the planner keeps about `table`, in `schema`
+    /// if given
+    ///
+    /// Names are quoted like for [`vacuum`](Self::vacuum).
+    pub fn analyze(&mut self, schema: Option<&str>, table: &str) -> QueryResult<()> {
+        self.batch_execute(&format!("ANALYZE {}", qualified_name(schema, table)))
+    }
+
+    /// Storage used by the main database and every attached one
+    pub fn database_size(&mut self) -> QueryResult<Vec<DatabaseSize>> {
+        let rows = diesel::sql_query("SELECT * FROM pragma_database_size()")
+            .load::<DatabaseSizeRow>(self)?;
+        Ok(rows.into_iter().map(DatabaseSize::from).collect())
+    }
+}
+
+fn qualified_name(schema: Option<&str>, table: &str) -> String {
+    match schema {
+        Some(schema) => format!("{}.{}", quote_identifier(schema), quote_identifier(table)),
+        None => quote_identifier(table),
+    }
+}
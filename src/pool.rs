@@ -0,0 +1,108 @@
+//! Pieces shared by the deadpool and bb8 connection managers
+//!
+//! Both pools hand out [`AsyncDuckDbConnection`]s, so opening, recycling and
+//! using a pooled connection never blocks the executor.
+
+use std::fmt;
+
+use diesel::connection::{SimpleConnection, TransactionManager};
+use diesel::result::{ConnectionError, ConnectionResult, Error as DieselError};
+use diesel::{Connection, QueryResult};
+
+use crate::{
+    AsyncDuckDbConnection, DuckDbConnection, DuckDbConnectionBuilder, DuckDbTransactionManager,
+};
+
+/// Error of the deadpool and bb8 connection managers
+#[derive(Debug)]
+pub enum PoolError {
+    /// An error occurred establishing the connection
+    ConnectionError(ConnectionError),
+
+    /// An error occurred running the init SQL or the health check
+    QueryError(DieselError),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::ConnectionError(e) => e.fmt(f),
+            PoolError::QueryError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// Opens the connections of a pool
+pub(crate) struct ConnectionFactory {
+    builder: DuckDbConnectionBuilder,
+    // Set in shared mode, pooled connections are clones of this one
+    shared: Option<AsyncDuckDbConnection>,
+    init_sql: Vec<String>,
+}
+
+impl ConnectionFactory {
+    pub(crate) fn new(builder: DuckDbConnectionBuilder) -> Self {
+        Self {
+            builder,
+            shared: None,
+            init_sql: Vec::new(),
+        }
+    }
+
+    pub(crate) fn shared(builder: DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        let connection = AsyncDuckDbConnection::from_connection(builder.establish()?)?;
+        Ok(Self {
+            shared: Some(connection),
+            ..Self::new(builder)
+        })
+    }
+
+    pub(crate) fn add_init_sql(&mut self, sql: String) {
+        self.init_sql.push(sql);
+    }
+
+    pub(crate) async fn connect(&self) -> Result<AsyncDuckDbConnection, PoolError> {
+        let connection = match &self.shared {
+            Some(shared) => shared.try_clone().await,
+            None => AsyncDuckDbConnection::from_builder(self.builder.clone()).await,
+        }
+        .map_err(PoolError::ConnectionError)?;
+
+        for sql in &self.init_sql {
+            connection
+                .batch_execute(sql.as_str())
+                .await
+                .map_err(PoolError::QueryError)?;
+        }
+        Ok(connection)
+    }
+}
+
+impl fmt::Debug for ConnectionFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionFactory")
+            .field("builder", &self.builder)
+            .field("shared", &self.shared.is_some())
+            .field("init_sql", &self.init_sql)
+            .finish()
+    }
+}
+
+/// Make a connection handed back to the pool reusable
+///
+/// A transaction left open, e.g. by a cancelled future, is rolled back before
+/// checking that the connection still answers `SELECT 1`.
+pub(crate) async fn recycle(connection: &AsyncDuckDbConnection) -> QueryResult<()> {
+    connection.interact(recycle_blocking).await
+}
+
+fn recycle_blocking(connection: &mut DuckDbConnection) -> QueryResult<()> {
+    if DuckDbTransactionManager::is_broken_transaction_manager(connection) {
+        // Fails if DuckDB already ended the transaction, the state is reset either way
+        let _ = connection.batch_execute("ROLLBACK");
+        *connection.transaction_state() = DuckDbTransactionManager::default();
+    }
+    connection.batch_execute("SELECT 1")
+}
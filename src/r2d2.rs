@@ -0,0 +1,70 @@
+//! Connection pooling with r2d2
+//!
+//! [`DuckDbConnection`] works with diesel's own
+//! [`ConnectionManager`](diesel::r2d2::ConnectionManager), which opens every
+//! pooled connection separately. That means each connection to `:memory:` gets
+//! its own database; [`DuckDbConnectionManager`] instead hands out clones of
+//! one `duckdb::Connection` so the whole pool shares a database.
+
+use std::sync::Mutex;
+
+use diesel::connection::{SimpleConnection, TransactionManager};
+use diesel::r2d2::{Error, ManageConnection, R2D2Connection};
+use diesel::result::{ConnectionError, ConnectionResult};
+use diesel::QueryResult;
+
+use crate::{DuckDbConnection, DuckDbConnectionBuilder, DuckDbTransactionManager};
+
+impl R2D2Connection for DuckDbConnection {
+    fn ping(&mut self) -> QueryResult<()> {
+        self.batch_execute("SELECT 1")
+    }
+
+    fn is_broken(&mut self) -> bool {
+        DuckDbTransactionManager::is_broken_transaction_manager(self)
+    }
+}
+
+/// r2d2 connection manager whose connections all share one DuckDB database
+///
+/// Pooled connections are made with [`DuckDbConnection::try_clone`].
+pub struct DuckDbConnectionManager {
+    connection: Mutex<DuckDbConnection>,
+}
+
+impl DuckDbConnectionManager {
+    /// Open the database described by `builder`, to be shared by all pooled connections
+    pub fn new(builder: DuckDbConnectionBuilder) -> ConnectionResult<Self> {
+        Ok(Self {
+            connection: Mutex::new(builder.establish()?),
+        })
+    }
+
+    /// Open the database at `database_url`, see [`DuckDbConnectionBuilder::from_url`]
+    pub fn from_url(database_url: &str) -> ConnectionResult<Self> {
+        Self::new(DuckDbConnectionBuilder::from_url(database_url)?)
+    }
+}
+
+impl ManageConnection for DuckDbConnectionManager {
+    type Connection = DuckDbConnection;
+    type Error = Error;
+
+    fn connect(&self) -> Result<DuckDbConnection, Error> {
+        self.connection
+            .lock()
+            .map_err(|_| {
+                ConnectionError::BadConnection("Shared DuckDB connection is poisoned".to_string())
+            })
+            .and_then(|connection| connection.try_clone())
+            .map_err(Error::ConnectionError)
+    }
+
+    fn is_valid(&self, conn: &mut DuckDbConnection) -> Result<(), Error> {
+        conn.ping().map_err(Error::QueryError)
+    }
+
+    fn has_broken(&self, conn: &mut DuckDbConnection) -> bool {
+        std::thread::panicking() || conn.is_broken()
+    }
+}
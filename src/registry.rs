@@ -0,0 +1,141 @@
+// Data of the functions registered with DuckDB from Rust
+//
+// DuckDB calls table and scalar functions registered through duckdb-rs
+// without any data from the code registering them. The data is kept here
+// instead, under a key the SQL calling the function passes as an argument.
+// The functions themselves are shared by all connections of a database and
+// registered once, see `register_once`.
+//
+// Keys are easily guessed, so every entry also belongs to the connection that
+// registered it. A connection gets a random token on its first registration,
+// kept in a variable of its DuckDB session that other connections can't
+// read, and the SQL calling a function passes that variable along with the
+// key. Lookups with the token of another connection are rejected.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use diesel::QueryResult;
+
+use crate::attach::quote_literal;
+use crate::error::MapDieselError;
+use crate::DuckDbConnection;
+
+/// The token of the calling connection, as an argument of a function call
+pub(crate) const OWNER_ARGUMENT: &str = "getvariable('__diesel_duckdb_connection')";
+
+const OWNER_VARIABLE: &str = "__diesel_duckdb_connection";
+
+struct Entry {
+    owner: String,
+    data: Arc<dyn Any + Send + Sync>,
+}
+
+static REGISTRY: Mutex<BTreeMap<u64, Entry>> = Mutex::new(BTreeMap::new());
+static NEXT_KEY: AtomicU64 = AtomicU64::new(1);
+
+fn registry() -> MutexGuard<'static, BTreeMap<u64, Entry>> {
+    // the map stays consistent even if a holder of the lock panicked
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl DuckDbConnection {
+    /// The token of this connection, made and set in its session on first use
+    pub(crate) fn registry_owner(&mut self) -> QueryResult<String> {
+        if let Some(owner) = &self.registry_owner {
+            return Ok(owner.clone());
+        }
+        let owner = self
+            .as_ref()
+            .query_row("SELECT CAST(gen_random_uuid() AS VARCHAR)", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .map_diesel_error()?;
+        self.as_ref()
+            .execute_batch(&format!(
+                "SET VARIABLE {} = {}",
+                OWNER_VARIABLE,
+                quote_literal(&owner)
+            ))
+            .map_diesel_error()?;
+        self.registry_owner = Some(owner.clone());
+        Ok(owner)
+    }
+}
+
+/// Registered data, removed from the registry when dropped
+pub(crate) struct Registration(u64);
+
+impl Registration {
+    /// Register `data` for the connection with the token `owner`
+    pub(crate) fn new<T: Send + Sync + 'static>(owner: String, data: Arc<T>) -> Self {
+        let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+        registry().insert(key, Entry { owner, data });
+        Self(key)
+    }
+
+    pub(crate) fn key(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        registry().remove(&self.0);
+    }
+}
+
+/// The data registered under `key` by the connection with the token
+/// `owner`, if it is still registered and a `T`
+pub(crate) fn lookup<T: Send + Sync + 'static>(
+    owner: &str,
+    key: i64,
+) -> Result<Arc<T>, &'static str> {
+    let entry = u64::try_from(key)
+        .ok()
+        .and_then(|key| {
+            registry()
+                .get(&key)
+                .map(|entry| (entry.owner == owner, Arc::clone(&entry.data)))
+        })
+        .ok_or("function called with the key of unregistered data")?;
+    match entry {
+        (true, data) => data
+            .downcast::<T>()
+            .map_err(|_| "function called with the key of data of another type"),
+        (false, _) => Err("function called with the key of data of another connection"),
+    }
+}
+
+/// Register the function `name` with `register`, unless the database of
+/// `connection` already has it
+///
+/// Functions are kept in the database's system catalog, which all of its
+/// connections share, and cannot be removed again.
+pub(crate) fn register_once(
+    connection: &duckdb::Connection,
+    name: &str,
+    register: impl FnOnce() -> duckdb::Result<()>,
+) -> QueryResult<()> {
+    let registered = || {
+        connection
+            .query_row(
+                "SELECT count(*) > 0 FROM duckdb_functions() WHERE function_name = ?",
+                [name],
+                |row| row.get::<_, bool>(0),
+            )
+            .map_diesel_error()
+    };
+
+    if registered()? {
+        return Ok(());
+    }
+    match register() {
+        Ok(()) => Ok(()),
+        // another connection of the database registered it in the meantime
+        Err(_) if registered()? => Ok(()),
+        Err(e) => Err(e).map_diesel_error(),
+    }
+}
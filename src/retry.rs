@@ -0,0 +1,103 @@
+//! Retrying transactions that conflict with concurrent writers
+
+use std::time::Duration;
+
+use diesel::connection::TransactionManager;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::{Connection, QueryResult};
+
+use crate::{DuckDbConnection, DuckDbTransactionManager};
+
+/// How often and how fast [`DuckDbConnection::transaction_with_retry`]
+/// retries a conflicting transaction
+///
+/// The delay before a retry starts at `backoff` and doubles with every
+/// further retry, up to `max_backoff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 3 attempts, waiting 10ms before the first retry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of times the transaction is run at most, including the first
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Upper bound of the delay between retries
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+impl DuckDbConnection {
+    /// Run `f` in a transaction, and run it again in a new transaction if it
+    /// conflicts with another transaction
+    ///
+    /// Conflicts are errors of kind
+    /// [`DatabaseErrorKind::SerializationFailure`], raised by a statement or
+    /// by the commit. Any other error is returned right away, as is the
+    /// conflict of the last attempt. `f` may run several times, so it must
+    /// not have side effects outside the transaction. Fails with
+    /// [`DieselError::AlreadyInTransaction`] inside another transaction.
+    pub fn transaction_with_retry<T, F>(&mut self, policy: &RetryPolicy, mut f: F) -> QueryResult<T>
+    where
+        F: FnMut(&mut Self) -> QueryResult<T>,
+    {
+        let depth =
+            DuckDbTransactionManager::transaction_manager_status_mut(self).transaction_depth()?;
+        if depth.is_some() {
+            return Err(DieselError::AlreadyInTransaction);
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.transaction(&mut f) {
+                Err(error) if is_conflict(&error) && attempt < policy.max_attempts => {
+                    std::thread::sleep(policy.delay(attempt - 1));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_conflict(error: &DieselError) -> bool {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => true,
+        DieselError::RollbackErrorOnCommit { commit_error, .. } => is_conflict(commit_error),
+        _ => false,
+    }
+}
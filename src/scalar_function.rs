@@ -0,0 +1,417 @@
+//! Rust closures as SQL functions, through DuckDB's scalar function API
+//!
+//! Every registration keeps its closure in the crate's registry and gets a
+//! temporary macro of the registered name. The macro calls a scalar function
+//! shared by all closures of the same signature, which takes the token of the
+//! connection and the registry key of the closure as its first arguments and
+//! calls the closure for every row of the chunks DuckDB hands it. Declare the function with diesel's
+//! `define_sql_function!` to use it in queries.
+
+use std::error::Error;
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::sql_types::{
+    is_nullable, BigInt, Binary, Bool, Date, Double, Float, Integer, Nullable, SingleValue,
+    SmallInt, SqlType, Text, Timestamp,
+};
+use diesel::QueryResult;
+use duckdb::core::{DataChunkHandle, FlatVector, LogicalTypeHandle, LogicalTypeId};
+use duckdb::ffi::duckdb_string_t;
+use duckdb::types::{DuckString, TimeUnit, Value};
+use duckdb::vscalar::{ScalarFunctionSignature, VScalar};
+use duckdb::vtab::arrow::WritableVector;
+
+use crate::attach::quote_identifier;
+use crate::error::{FromDuckDbValueError, MapQueryError};
+use crate::registry::{self, Registration};
+use crate::value::FromDuckDbValue;
+use crate::virtual_table::{write_value, VirtualColumnType};
+use crate::DuckDbConnection;
+
+/// Error returned by the closure of a scalar function
+pub type ScalarFunctionError = Box<dyn Error + Send + Sync>;
+
+// A registered closure, whatever its type
+type Closure<Args, Ret> = Box<
+    dyn Fn(<Args as ScalarArgs>::Values) -> Result<<Ret as ScalarType>::Value, ScalarFunctionError>
+        + Send
+        + Sync,
+>;
+
+/// A SQL type of arguments and results of scalar functions
+///
+/// Implemented for `Bool`, `SmallInt`, `Integer`, `BigInt`, `Float`,
+/// `Double`, `Text`, `Binary`, `Date` and `Timestamp`, and `Nullable` of them.
+pub trait ScalarType: SingleValue {
+    /// The Rust type the closure takes or returns for this type
+    type Value;
+
+    #[doc(hidden)]
+    const NULLABLE: bool = false;
+
+    #[doc(hidden)]
+    fn column_type() -> VirtualColumnType;
+
+    // `None` for NULL, unless the type is nullable
+    #[doc(hidden)]
+    fn from_value(value: Value) -> Result<Option<Self::Value>, FromDuckDbValueError>;
+
+    #[doc(hidden)]
+    fn to_value(value: Self::Value) -> Value;
+}
+
+macro_rules! scalar_type {
+    ($sql:ty, $rust:ty, $column:ident, $to_value:expr) => {
+        impl ScalarType for $sql {
+            type Value = $rust;
+
+            fn column_type() -> VirtualColumnType {
+                VirtualColumnType::$column
+            }
+
+            fn from_value(value: Value) -> Result<Option<$rust>, FromDuckDbValueError> {
+                if value == Value::Null {
+                    return Ok(None);
+                }
+                <$rust>::from_duckdb_value(&value).map(Some)
+            }
+
+            fn to_value(value: $rust) -> Value {
+                $to_value(value)
+            }
+        }
+    };
+}
+
+scalar_type!(Bool, bool, Boolean, Value::from);
+scalar_type!(SmallInt, i16, SmallInt, Value::from);
+scalar_type!(Integer, i32, Integer, Value::from);
+scalar_type!(BigInt, i64, BigInt, Value::from);
+scalar_type!(Float, f32, Float, Value::from);
+scalar_type!(Double, f64, Double, Value::from);
+scalar_type!(Text, String, Text, Value::from);
+scalar_type!(Binary, Vec<u8>, Blob, Value::from);
+scalar_type!(Date, NaiveDate, Date, |date: NaiveDate| {
+    // days since the Unix epoch, which fit into i32 for every NaiveDate
+    let days = (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+    Value::Date32(days as i32)
+});
+scalar_type!(
+    Timestamp,
+    NaiveDateTime,
+    Timestamp,
+    |timestamp: NaiveDateTime| {
+        Value::Timestamp(
+            TimeUnit::Microsecond,
+            timestamp.and_utc().timestamp_micros(),
+        )
+    }
+);
+
+impl<T> ScalarType for Nullable<T>
+where
+    T: ScalarType + SqlType<IsNull = is_nullable::NotNull>,
+{
+    type Value = Option<T::Value>;
+
+    const NULLABLE: bool = true;
+
+    fn column_type() -> VirtualColumnType {
+        T::column_type()
+    }
+
+    fn from_value(value: Value) -> Result<Option<Self::Value>, FromDuckDbValueError> {
+        T::from_value(value).map(Some)
+    }
+
+    fn to_value(value: Self::Value) -> Value {
+        value.map_or(Value::Null, T::to_value)
+    }
+}
+
+/// The argument types of a scalar function, a tuple of up to six [`ScalarType`]s
+pub trait ScalarArgs {
+    /// The Rust values the closure takes, a tuple of the arguments' values
+    type Values;
+
+    // the type of every argument, and whether it is nullable
+    #[doc(hidden)]
+    fn parameters() -> Vec<(VirtualColumnType, bool)>;
+
+    // `None` if an argument of a type that is not nullable is NULL
+    #[doc(hidden)]
+    fn read(
+        vectors: &[FlatVector],
+        row: usize,
+    ) -> Result<Option<Self::Values>, FromDuckDbValueError>;
+}
+
+macro_rules! scalar_args {
+    ($($arg:ident $index:tt),+) => {
+        impl<$($arg: ScalarType),+> ScalarArgs for ($($arg,)+) {
+            type Values = ($($arg::Value,)+);
+
+            fn parameters() -> Vec<(VirtualColumnType, bool)> {
+                vec![$(($arg::column_type(), $arg::NULLABLE)),+]
+            }
+
+            fn read(
+                vectors: &[FlatVector],
+                row: usize,
+            ) -> Result<Option<Self::Values>, FromDuckDbValueError> {
+                Ok(Some(($(
+                    match $arg::from_value(read_value(&vectors[$index], row, $arg::column_type()))? {
+                        Some(value) => value,
+                        None => return Ok(None),
+                    },
+                )+)))
+            }
+        }
+    };
+}
+
+scalar_args!(A 0);
+scalar_args!(A 0, B 1);
+scalar_args!(A 0, B 1, C 2);
+scalar_args!(A 0, B 1, C 2, D 3);
+scalar_args!(A 0, B 1, C 2, D 3, E 4);
+scalar_args!(A 0, B 1, C 2, D 3, E 4, G 5);
+
+impl DuckDbConnection {
+    /// Make `function` callable as the SQL function `name` on this connection
+    ///
+    /// `Args` is the tuple of the SQL types of the arguments, `Ret` the SQL
+    /// type of the result, as in the matching `define_sql_function!`:
+    ///
+    /// ```ignore
+    /// define_sql_function!(fn initials(name: Text) -> Text);
+    ///
+    /// conn.register_scalar_function::<(Text,), Text, _>("initials", |(name,)| {
+    ///     Ok(name.split_whitespace().filter_map(|w| w.chars().next()).collect())
+    /// })?;
+    /// let initials = users::table.select(initials(users::name)).load::<String>(&mut conn)?;
+    /// ```
+    ///
+    /// The result is NULL without calling `function` if an argument whose
+    /// type is not `Nullable` is NULL. Errors returned by `function`, and
+    /// panics in it, fail the query calling it.
+    ///
+    /// The function is a temporary macro of this connection, registering
+    /// `name` again replaces it. The macro calls a scalar function named
+    /// after the argument and result types, which is registered with the
+    /// database on first use and shared by all of its connections.
+    pub fn register_scalar_function<Args, Ret, F>(
+        &mut self,
+        name: &str,
+        function: F,
+    ) -> QueryResult<()>
+    where
+        Args: ScalarArgs + 'static,
+        Ret: ScalarType + 'static,
+        F: Fn(Args::Values) -> Result<Ret::Value, ScalarFunctionError> + Send + Sync + 'static,
+    {
+        let closure: Closure<Args, Ret> = Box::new(function);
+        let registration = Registration::new(self.registry_owner()?, Arc::new(closure));
+        let key = registration.key();
+
+        let parameters = Args::parameters();
+        let scalar = signature_name("function", &parameters, (Ret::column_type(), Ret::NULLABLE));
+        registry::register_once(self.as_ref(), &scalar, || {
+            self.as_ref()
+                .register_scalar_function::<ClosureFunction<Args, Ret>>(&scalar)
+        })?;
+
+        let params = (0..parameters.len())
+            .map(|i| format!("arg{}", i))
+            .collect::<Vec<_>>();
+        // DuckDB makes calls with a constant NULL argument NULL without
+        // calling the function, so nullable arguments are passed in a struct
+        let args = params
+            .iter()
+            .zip(&parameters)
+            .map(|(param, (_, nullable))| match nullable {
+                true => format!("{{'value': {}}}", param),
+                false => param.clone(),
+            })
+            .collect::<Vec<_>>();
+        let sql = format!(
+            "CREATE OR REPLACE TEMP MACRO {}({}) AS {}({}, {}, {})",
+            quote_identifier(name),
+            params.join(", "),
+            quote_identifier(&scalar),
+            registry::OWNER_ARGUMENT,
+            key,
+            args.join(", ")
+        );
+        self.as_ref().execute_batch(&sql).map_query_error(&sql)?;
+
+        self.scalar_functions.insert(name.to_string(), registration);
+        Ok(())
+    }
+
+    /// Remove the function `name` registered with
+    /// [`register_scalar_function`](Self::register_scalar_function)
+    ///
+    /// Does nothing if no such function is registered.
+    pub fn unregister_scalar_function(&mut self, name: &str) -> QueryResult<()> {
+        if self.scalar_functions.remove(name).is_some() {
+            let sql = format!("DROP MACRO IF EXISTS temp.{}", quote_identifier(name));
+            self.as_ref().execute_batch(&sql).map_query_error(&sql)?;
+        }
+        Ok(())
+    }
+}
+
+// Name of the `kind` of function calling the closures or aggregates taking
+// `parameters` and returning `result`, e.g.
+// `__diesel_duckdb_function_integer_nullable_text_to_text`
+pub(crate) fn signature_name(
+    kind: &str,
+    parameters: &[(VirtualColumnType, bool)],
+    result: (VirtualColumnType, bool),
+) -> String {
+    let type_name = |(column_type, nullable): &(VirtualColumnType, bool)| {
+        let name = format!("{:?}", column_type).to_lowercase();
+        match nullable {
+            true => format!("nullable_{}", name),
+            false => name,
+        }
+    };
+    let parameters = parameters.iter().map(type_name).collect::<Vec<_>>();
+    format!(
+        "__diesel_duckdb_{}_{}_to_{}",
+        kind,
+        parameters.join("_"),
+        type_name(&result)
+    )
+}
+
+struct ClosureFunction<Args, Ret>(PhantomData<(Args, Ret)>);
+
+impl<Args, Ret> VScalar for ClosureFunction<Args, Ret>
+where
+    Args: ScalarArgs + 'static,
+    Ret: ScalarType + 'static,
+{
+    type State = ();
+
+    unsafe fn invoke(
+        _: &Self::State,
+        input: &mut DataChunkHandle,
+        output: &mut dyn WritableVector,
+    ) -> Result<(), Box<dyn Error>> {
+        let rows = input.len();
+        if rows == 0 {
+            return Ok(());
+        }
+        let function = lookup_registration::<Closure<Args, Ret>>(
+            &input.flat_vector(0),
+            &input.flat_vector(1),
+        )?;
+
+        let vectors = Args::parameters()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, nullable))| match nullable {
+                true => input.struct_vector(i + 2).child(0, rows),
+                false => input.flat_vector(i + 2),
+            })
+            .collect::<Vec<_>>();
+        let mut output = output.flat_vector();
+        for row in 0..rows {
+            let Some(args) = Args::read(&vectors, row)? else {
+                output.set_null(row);
+                continue;
+            };
+            let value = catch_panic("scalar function", || function(args))?;
+            write_value(&mut output, row, Ret::column_type(), &Ret::to_value(value))?;
+        }
+        Ok(())
+    }
+
+    fn signatures() -> Vec<ScalarFunctionSignature> {
+        let parameters =
+            [LogicalTypeId::Varchar, LogicalTypeId::Bigint]
+                .map(LogicalTypeHandle::from)
+                .into_iter()
+                .chain(Args::parameters().into_iter().map(
+                    |(column_type, nullable)| match nullable {
+                        true => {
+                            LogicalTypeHandle::struct_type(&[("value", column_type.logical_type())])
+                        }
+                        false => column_type.logical_type(),
+                    },
+                ))
+                .collect();
+        vec![ScalarFunctionSignature::exact(
+            parameters,
+            Ret::column_type().logical_type(),
+        )]
+    }
+}
+
+/// The data registered for a function call whose first arguments, the same
+/// in every row, are the token of the calling connection in `owner` and the
+/// registry key in `key`
+pub(crate) fn lookup_registration<T: Send + Sync + 'static>(
+    owner: &FlatVector,
+    key: &FlatVector,
+) -> Result<Arc<T>, Box<dyn Error>> {
+    let owner = match read_value(owner, 0, VirtualColumnType::Text) {
+        Value::Text(owner) => owner,
+        _ => return Err("function called without the token of a connection".into()),
+    };
+    let key = key.as_slice_with_len::<i64>(1)[0];
+    Ok(registry::lookup::<T>(&owner, key)?)
+}
+
+/// Run `f`, turning a panic into an error, as panics must not unwind into DuckDB
+pub(crate) fn catch_panic<T>(
+    kind: &str,
+    f: impl FnOnce() -> Result<T, ScalarFunctionError>,
+) -> Result<T, Box<dyn Error>> {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(|e| -> Box<dyn Error> { e }),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            Err(format!("{} panicked: {}", kind, message).into())
+        }
+    }
+}
+
+/// Read `row` of a vector of `column_type`, NULL as `Value::Null`
+fn read_value(vector: &FlatVector, row: usize, column_type: VirtualColumnType) -> Value {
+    if vector.row_is_null(row as u64) {
+        return Value::Null;
+    }
+    match column_type {
+        VirtualColumnType::Boolean => Value::Boolean(get(vector, row)),
+        VirtualColumnType::TinyInt => Value::TinyInt(get(vector, row)),
+        VirtualColumnType::SmallInt => Value::SmallInt(get(vector, row)),
+        VirtualColumnType::Integer => Value::Int(get(vector, row)),
+        VirtualColumnType::BigInt => Value::BigInt(get(vector, row)),
+        VirtualColumnType::Float => Value::Float(get(vector, row)),
+        VirtualColumnType::Double => Value::Double(get(vector, row)),
+        VirtualColumnType::Text => {
+            let mut text = get::<duckdb_string_t>(vector, row);
+            Value::Text(DuckString::new(&mut text).as_str().into_owned())
+        }
+        VirtualColumnType::Blob => {
+            let mut blob = get::<duckdb_string_t>(vector, row);
+            Value::Blob(DuckString::new(&mut blob).as_bytes().to_vec())
+        }
+        VirtualColumnType::Date => Value::Date32(get(vector, row)),
+        VirtualColumnType::Timestamp => Value::Timestamp(TimeUnit::Microsecond, get(vector, row)),
+    }
+}
+
+fn get<T: Copy>(vector: &FlatVector, row: usize) -> T {
+    vector.as_slice::<T>()[row]
+}
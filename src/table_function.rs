@@ -0,0 +1,227 @@
+//! Files as query sources through `read_parquet`, `read_csv` and `read_json`
+//!
+//! [`table_function!`](crate::table_function!) declares the columns of the
+//! rows a table function returns, the way `table!` does for a table. A query
+//! reads the declared table from a function with
+//! [`TableFunctionSource::read_from`], which puts the function call in the
+//! `FROM` clause under the name of the table.
+
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
+use diesel::sql_types::{BigInt, Bool, Double, Text};
+use diesel::{QueryResult, Table};
+
+use crate::table_source::{FromClauseSource, TableSource};
+use crate::DuckDb;
+
+/// Declare the columns of a table function, with the syntax of `table!`
+///
+/// The declared table is queried through
+/// [`TableFunctionSource::read_from`], and can then be selected, filtered
+/// and joined like any other table. Joins need an explicit `ON` clause, and
+/// `allow_tables_to_appear_in_same_query!` for the tables involved. Columns
+/// are matched by name, so a file may have more columns than declared. The
+/// primary key is not checked by DuckDB.
+///
+/// ```ignore
+/// diesel_duckdb::table_function! {
+///     events (event_id) {
+///         event_id -> Integer,
+///         user_id -> Integer,
+///         kind -> Text,
+///     }
+/// }
+///
+/// let logins = events::table
+///     .read_from(TableFunction::read_parquet("events/*.parquet"))
+///     .filter(events::kind.eq("login"))
+///     .select(events::user_id)
+///     .load::<i32>(&mut conn)?;
+/// ```
+#[macro_export]
+macro_rules! table_function {
+    (
+        $(#[$meta:meta])*
+        $name:ident $(($($pk:ident),+ $(,)?))? {
+            $($(#[$column_meta:meta])* $column:ident -> $column_type:ty),+ $(,)?
+        }
+    ) => {
+        diesel::table! {
+            $(#[$meta])*
+            $name $(($($pk),+))? {
+                $($(#[$column_meta])* $column -> $column_type,)+
+            }
+        }
+
+        impl $crate::table_function::TableFunctionSource for $name::table {}
+
+        impl diesel::query_source::AppearsInFromClause<$name::table>
+            for $crate::table_function::FromTableFunction<$name::table>
+        {
+            type Count = diesel::query_source::Once;
+        }
+
+        $(
+            impl diesel::SelectableExpression<$crate::table_function::FromTableFunction<$name::table>>
+                for $name::$column
+            {
+            }
+        )+
+    };
+}
+
+/// A table declared with [`table_function!`](crate::table_function!)
+pub trait TableFunctionSource: Table + QueryFragment<DuckDb> + Clone {
+    /// The table, with its rows read from `function`
+    fn read_from(self, function: TableFunction) -> FromTableFunction<Self> {
+        TableSource::new(self, function)
+    }
+}
+
+/// A call of one of DuckDB's file reading table functions
+///
+/// Paths and option values are bound as parameters, not written into the SQL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableFunction {
+    function: &'static str,
+    paths: Vec<String>,
+    options: Vec<(String, TableFunctionValue)>,
+}
+
+/// Value of a named parameter of a [`TableFunction`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableFunctionValue {
+    Bool(bool),
+    BigInt(i64),
+    Double(f64),
+    Text(String),
+}
+
+impl From<bool> for TableFunctionValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for TableFunctionValue {
+    fn from(value: i32) -> Self {
+        Self::BigInt(value.into())
+    }
+}
+
+impl From<i64> for TableFunctionValue {
+    fn from(value: i64) -> Self {
+        Self::BigInt(value)
+    }
+}
+
+impl From<f64> for TableFunctionValue {
+    fn from(value: f64) -> Self {
+        Self::Double(value)
+    }
+}
+
+impl From<&str> for TableFunctionValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for TableFunctionValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl TableFunction {
+    /// `read_parquet(path)`, needs the `parquet` feature
+    pub fn read_parquet(path: impl Into<String>) -> Self {
+        Self::new("read_parquet", path.into())
+    }
+
+    /// `read_csv(path)`, which detects the dialect and column types of the files
+    pub fn read_csv(path: impl Into<String>) -> Self {
+        Self::new("read_csv", path.into())
+    }
+
+    /// `read_json(path)`, needs the `json` feature
+    pub fn read_json(path: impl Into<String>) -> Self {
+        Self::new("read_json", path.into())
+    }
+
+    fn new(function: &'static str, path: String) -> Self {
+        Self {
+            function,
+            paths: vec![path],
+            options: Vec::new(),
+        }
+    }
+
+    /// Read the files at `path` as well
+    ///
+    /// Every path may be a glob, e.g. `data/*.parquet`.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Pass the named parameter `name`, e.g. `option("header", true)` or
+    /// `option("hive_partitioning", true)`
+    pub fn option(mut self, name: impl Into<String>, value: impl Into<TableFunctionValue>) -> Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl QueryFragment<DuckDb> for TableFunction {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, DuckDb>) -> QueryResult<()> {
+        out.push_sql(self.function);
+        out.push_sql("(");
+        if let [path] = self.paths.as_slice() {
+            out.push_bind_param::<Text, str>(path)?;
+        } else {
+            out.push_sql("[");
+            for (i, path) in self.paths.iter().enumerate() {
+                if i > 0 {
+                    out.push_sql(", ");
+                }
+                out.push_bind_param::<Text, str>(path)?;
+            }
+            out.push_sql("]");
+        }
+        for (name, value) in &self.options {
+            out.push_sql(", ");
+            out.push_identifier(name)?;
+            out.push_sql(" = ");
+            match value {
+                TableFunctionValue::Bool(value) => out.push_bind_param::<Bool, _>(value)?,
+                TableFunctionValue::BigInt(value) => out.push_bind_param::<BigInt, _>(value)?,
+                TableFunctionValue::Double(value) => out.push_bind_param::<Double, _>(value)?,
+                TableFunctionValue::Text(value) => out.push_bind_param::<Text, str>(value)?,
+            }
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// A table with its rows read from a table function, see
+/// [`TableFunctionSource::read_from`]
+pub type FromTableFunction<T> = TableSource<T, TableFunction>;
+
+impl QueryId for TableFunction {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<T: TableFunctionSource> FromClauseSource<T> for TableFunction {
+    fn walk_from_clause<'b>(
+        &'b self,
+        table: &'b T,
+        mut out: AstPass<'_, 'b, DuckDb>,
+    ) -> QueryResult<()> {
+        self.walk_ast(out.reborrow())?;
+        out.push_sql(" AS ");
+        table.walk_ast(out.reborrow())
+    }
+}
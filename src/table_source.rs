@@ -0,0 +1,118 @@
+//! Tables read from something other than their own name
+//!
+//! [`TableSource`] wraps a table and writes its `FROM` clause through a
+//! [`FromClauseSource`], e.g. qualified with an attached database
+//! ([`InCatalog`](crate::InCatalog)) or as the call of a table function
+//! ([`FromTableFunction`](crate::FromTableFunction)). Everything else, such as
+//! its columns and primary key, is the wrapped table's.
+
+use diesel::expression::{Expression, ValidGrouping};
+use diesel::query_builder::{
+    AsQuery, AstPass, FromClause, QueryFragment, QueryId, SelectStatement,
+};
+use diesel::query_source::{QuerySource, TableNotEqual};
+use diesel::{QueryResult, SelectableExpression, Table};
+
+use crate::DuckDb;
+
+/// Writes the `FROM` clause of a [`TableSource`] wrapping `T`
+pub trait FromClauseSource<T>: QueryId + Clone {
+    /// Write the `FROM` clause, which must name the rows `table`
+    fn walk_from_clause<'b>(
+        &'b self,
+        table: &'b T,
+        out: AstPass<'_, 'b, DuckDb>,
+    ) -> QueryResult<()>;
+}
+
+/// A table with its `FROM` clause written by `S`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableSource<T, S> {
+    table: T,
+    source: S,
+}
+
+impl<T, S> TableSource<T, S> {
+    pub(crate) fn new(table: T, source: S) -> Self {
+        Self { table, source }
+    }
+}
+
+impl<T, S> QueryId for TableSource<T, S>
+where
+    T: QueryId,
+    S: QueryId,
+{
+    type QueryId = TableSource<T::QueryId, S::QueryId>;
+    const HAS_STATIC_QUERY_ID: bool = T::HAS_STATIC_QUERY_ID && S::HAS_STATIC_QUERY_ID;
+}
+
+impl<T, S> QuerySource for TableSource<T, S>
+where
+    T: Table + Clone,
+    S: FromClauseSource<T>,
+    T::DefaultSelection: ValidGrouping<()> + SelectableExpression<Self>,
+{
+    type FromClause = Self;
+    type DefaultSelection = T::DefaultSelection;
+
+    fn from_clause(&self) -> Self::FromClause {
+        self.clone()
+    }
+
+    fn default_selection(&self) -> Self::DefaultSelection {
+        self.table.default_selection()
+    }
+}
+
+impl<T, S> QueryFragment<DuckDb> for TableSource<T, S>
+where
+    S: FromClauseSource<T>,
+{
+    fn walk_ast<'b>(&'b self, out: AstPass<'_, 'b, DuckDb>) -> QueryResult<()> {
+        self.source.walk_from_clause(&self.table, out)
+    }
+}
+
+impl<T, S> AsQuery for TableSource<T, S>
+where
+    T: Table + Clone,
+    S: FromClauseSource<T>,
+    T::DefaultSelection: ValidGrouping<()> + SelectableExpression<Self>,
+{
+    type SqlType = <T::DefaultSelection as Expression>::SqlType;
+    type Query = SelectStatement<FromClause<Self>>;
+
+    fn as_query(self) -> Self::Query {
+        SelectStatement::simple(self)
+    }
+}
+
+impl<T, S> Table for TableSource<T, S>
+where
+    T: Table + Clone,
+    S: FromClauseSource<T>,
+    T::PrimaryKey: SelectableExpression<Self>,
+    T::AllColumns: SelectableExpression<Self>,
+    T::DefaultSelection: ValidGrouping<()> + SelectableExpression<Self>,
+{
+    type PrimaryKey = T::PrimaryKey;
+    type AllColumns = T::AllColumns;
+
+    fn primary_key(&self) -> Self::PrimaryKey {
+        self.table.primary_key()
+    }
+
+    fn all_columns() -> Self::AllColumns {
+        T::all_columns()
+    }
+}
+
+// Tables allowed in the same query as the table are allowed with it wrapped
+impl<T, S, U> TableNotEqual<U> for TableSource<T, S>
+where
+    T: TableNotEqual<U>,
+    U: Table,
+    Self: Table,
+{
+}
@@ -0,0 +1,326 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double, Integer, Nullable, Text};
+
+use super::schema::orders;
+use super::{setup_basic_connection, setup_orders_with_sample_data};
+use crate::{AggregateFunction, DuckDbConnection, ScalarFunctionError};
+
+diesel::define_sql_function! {
+    #[aggregate]
+    fn weighted_average(value: Nullable<Double>, weight: Nullable<Integer>) -> Nullable<Double>;
+}
+
+diesel::define_sql_function! {
+    #[aggregate]
+    fn joined(value: Nullable<Text>) -> Text;
+}
+
+struct WeightedAverage;
+
+impl AggregateFunction for WeightedAverage {
+    type Args = (Double, Integer);
+    type Ret = Nullable<Double>;
+    type State = (f64, i64);
+
+    fn init(&self) -> (f64, i64) {
+        (0.0, 0)
+    }
+
+    fn update(
+        &self,
+        state: &mut (f64, i64),
+        (value, weight): (f64, i32),
+    ) -> Result<(), ScalarFunctionError> {
+        if weight < 0 {
+            return Err(format!("negative weight {}", weight).into());
+        }
+        state.0 += value * f64::from(weight);
+        state.1 += i64::from(weight);
+        Ok(())
+    }
+
+    fn combine(
+        &self,
+        state: &mut (f64, i64),
+        other: (f64, i64),
+    ) -> Result<(), ScalarFunctionError> {
+        state.0 += other.0;
+        state.1 += other.1;
+        Ok(())
+    }
+
+    fn finalize(&self, (sum, weights): (f64, i64)) -> Result<Option<f64>, ScalarFunctionError> {
+        Ok((weights != 0).then(|| sum / weights as f64))
+    }
+}
+
+// Joins its values, NULL as "-", and the batches it combined with "|"
+struct Joined;
+
+impl AggregateFunction for Joined {
+    type Args = (Nullable<Text>,);
+    type Ret = Text;
+    type State = String;
+
+    fn init(&self) -> String {
+        String::new()
+    }
+
+    fn update(
+        &self,
+        state: &mut String,
+        (value,): (Option<String>,),
+    ) -> Result<(), ScalarFunctionError> {
+        state.push_str(value.as_deref().unwrap_or("-"));
+        Ok(())
+    }
+
+    fn combine(&self, state: &mut String, other: String) -> Result<(), ScalarFunctionError> {
+        state.push('|');
+        state.push_str(&other);
+        Ok(())
+    }
+
+    fn finalize(&self, state: String) -> Result<String, ScalarFunctionError> {
+        Ok(state)
+    }
+}
+
+#[test]
+fn test_group_by() {
+    let mut conn = setup_orders_with_sample_data();
+    conn.register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+
+    let averages = orders::table
+        .group_by(orders::user_id)
+        .select((
+            orders::user_id,
+            weighted_average(orders::price, orders::quantity),
+        ))
+        .order(orders::user_id)
+        .load::<(Option<i32>, Option<f64>)>(&mut conn)
+        .unwrap();
+    assert_eq!(
+        averages,
+        [
+            (Some(1), Some((999.99 + 2.0 * 25.50) / 3.0)),
+            (Some(2), Some(75.0)),
+        ]
+    );
+}
+
+#[test]
+fn test_null_arguments() {
+    let mut conn = setup_orders_with_sample_data();
+    conn.batch_execute(
+        "INSERT INTO orders (order_id, user_id, product_name, quantity, price) VALUES \
+         (4, 2, NULL, NULL, 10.0), (5, 3, NULL, 1, NULL)",
+    )
+    .unwrap();
+    conn.register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+    conn.register_aggregate_function("joined", Joined).unwrap();
+
+    // rows with NULL arguments that are not nullable are skipped
+    let averages = orders::table
+        .group_by(orders::user_id)
+        .select(weighted_average(orders::price, orders::quantity))
+        .order(orders::user_id)
+        .load::<Option<f64>>(&mut conn)
+        .unwrap();
+    assert_eq!(averages[1..], [Some(75.0), None]);
+
+    let names = orders::table
+        .filter(orders::user_id.eq(2))
+        .select(joined(orders::product_name))
+        .get_result::<String>(&mut conn)
+        .unwrap();
+    assert!(names == "Keyboard-" || names == "-Keyboard");
+}
+
+#[test]
+fn test_without_rows() {
+    let mut conn = setup_orders_with_sample_data();
+    conn.register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+
+    let average = orders::table
+        .filter(orders::order_id.gt(10))
+        .select(weighted_average(orders::price, orders::quantity))
+        .get_result::<Option<f64>>(&mut conn)
+        .unwrap();
+    assert_eq!(average, None);
+}
+
+#[test]
+fn test_groups_folded_in_parallel() {
+    let mut conn = setup_basic_connection();
+    conn.register_aggregate_function("joined", Joined).unwrap();
+
+    // DuckDB folds the rows of large groups on several threads and merges
+    // their states with `combine`
+    let joined = diesel::select(diesel::dsl::sql::<Text>(
+        "(SELECT joined('x') FROM range(1000000))",
+    ))
+    .get_result::<String>(&mut conn)
+    .unwrap();
+    assert_eq!(joined.replace('|', "").len(), 1000000);
+
+    let count = diesel::select(diesel::dsl::sql::<BigInt>(
+        "(SELECT count(*) FROM (SELECT i % 3 AS g, joined(i::VARCHAR) FROM range(5000) t(i) GROUP BY g))",
+    ))
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+    assert_eq!(count, 3);
+}
+
+#[test]
+fn test_window() {
+    struct Total;
+
+    impl AggregateFunction for Total {
+        type Args = (BigInt,);
+        type Ret = BigInt;
+        type State = i64;
+
+        fn init(&self) -> i64 {
+            0
+        }
+
+        fn update(&self, state: &mut i64, (value,): (i64,)) -> Result<(), ScalarFunctionError> {
+            *state += value;
+            Ok(())
+        }
+
+        fn combine(&self, state: &mut i64, other: i64) -> Result<(), ScalarFunctionError> {
+            *state += other;
+            Ok(())
+        }
+
+        fn finalize(&self, state: i64) -> Result<i64, ScalarFunctionError> {
+            Ok(state)
+        }
+    }
+
+    let mut conn = setup_basic_connection();
+    conn.register_aggregate_function("total", Total).unwrap();
+
+    // the states of a row are merged into the frames of several rows
+    let totals = diesel::select(diesel::dsl::sql::<Text>(
+        "(SELECT string_agg(t::VARCHAR, ',' ORDER BY i) FROM (SELECT i, total(i) \
+         OVER (ORDER BY i ROWS BETWEEN 2 PRECEDING AND CURRENT ROW) AS t FROM range(6) r(i)))",
+    ))
+    .get_result::<String>(&mut conn)
+    .unwrap();
+    assert_eq!(totals, "0,1,3,6,9,12");
+
+    let wrong = diesel::select(diesel::dsl::sql::<BigInt>(
+        "(SELECT count(*) FROM (SELECT total(i) OVER w AS t, sum(i) OVER w AS s FROM range(5000) r(i) \
+         WINDOW w AS (ORDER BY i ROWS BETWEEN 700 PRECEDING AND 300 FOLLOWING)) WHERE t <> s)",
+    ))
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+    assert_eq!(wrong, 0);
+}
+
+#[test]
+fn test_shared_with_clones() {
+    let mut conn = setup_orders_with_sample_data();
+    let mut clone = conn.try_clone().unwrap();
+    clone
+        .register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+    conn.register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+    drop(conn);
+
+    let average = orders::table
+        .filter(orders::user_id.eq(2))
+        .select(weighted_average(orders::price, orders::quantity))
+        .get_result::<Option<f64>>(&mut clone)
+        .unwrap();
+    assert_eq!(average, Some(75.0));
+}
+
+#[test]
+fn test_needs_a_database_opened_by_the_crate() {
+    let mut conn =
+        DuckDbConnection::establish_with_flags(":memory:", duckdb::Config::default()).unwrap();
+
+    let error = conn
+        .register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Aggregate functions can only be registered"));
+}
+
+#[test]
+fn test_error_fails_query() {
+    let mut conn = setup_orders_with_sample_data();
+    conn.batch_execute("UPDATE orders SET quantity = -1 WHERE order_id = 3")
+        .unwrap();
+    conn.register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+
+    let error = orders::table
+        .group_by(orders::user_id)
+        .select(weighted_average(orders::price, orders::quantity))
+        .load::<Option<f64>>(&mut conn)
+        .unwrap_err();
+    assert!(error.to_string().contains("negative weight -1"));
+}
+
+#[test]
+fn test_panic_fails_query() {
+    struct Panics;
+
+    impl AggregateFunction for Panics {
+        type Args = (Integer,);
+        type Ret = Integer;
+        type State = ();
+
+        fn init(&self) {}
+
+        fn update(&self, _: &mut (), _: (i32,)) -> Result<(), ScalarFunctionError> {
+            panic!("boom");
+        }
+
+        fn combine(&self, _: &mut (), _: ()) -> Result<(), ScalarFunctionError> {
+            Ok(())
+        }
+
+        fn finalize(&self, _: ()) -> Result<i32, ScalarFunctionError> {
+            Ok(0)
+        }
+    }
+
+    let mut conn = setup_basic_connection();
+    conn.register_aggregate_function("panics", Panics).unwrap();
+
+    let error = conn
+        .batch_execute("SELECT panics(i::INTEGER) FROM range(3) t(i)")
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("aggregate function panicked: boom"));
+}
+
+#[test]
+fn test_unregister() {
+    let mut conn = setup_orders_with_sample_data();
+    conn.register_aggregate_function("weighted_average", WeightedAverage)
+        .unwrap();
+    conn.unregister_aggregate_function("weighted_average")
+        .unwrap();
+
+    assert!(orders::table
+        .select(weighted_average(orders::price, orders::quantity))
+        .get_result::<Option<f64>>(&mut conn)
+        .is_err());
+    // unknown names are ignored
+    conn.unregister_aggregate_function("weighted_average")
+        .unwrap();
+}
@@ -0,0 +1,146 @@
+use std::future::poll_fn;
+use std::pin::Pin;
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use futures_core::Stream;
+
+use super::schema::users;
+use super::setup_users_table;
+use crate::async_connection::DuckDbRowStream;
+use crate::{AsyncDuckDbConnection, DuckDbConnection};
+
+async fn setup_async_connection() -> AsyncDuckDbConnection {
+    let conn = AsyncDuckDbConnection::establish(":memory:").await.unwrap();
+    conn.interact(|conn| {
+        setup_users_table(conn);
+        conn.batch_execute("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob')")
+    })
+    .await
+    .unwrap();
+    conn
+}
+
+async fn next<U>(stream: &mut DuckDbRowStream<U>) -> Option<QueryResult<U>> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn test_async_load_and_execute() {
+    let conn = setup_async_connection().await;
+
+    let inserted = conn
+        .execute(
+            diesel::insert_into(users::table).values((users::id.eq(3), users::name.eq("Carol"))),
+        )
+        .await
+        .unwrap();
+    assert_eq!(inserted, 1);
+
+    let names = conn
+        .load::<_, Option<String>>(users::table.select(users::name).order(users::id))
+        .await
+        .unwrap();
+    assert_eq!(
+        names,
+        vec![
+            Some("Alice".to_string()),
+            Some("Bob".to_string()),
+            Some("Carol".to_string())
+        ]
+    );
+
+    let count: i64 = conn.get_result(users::table.count()).await.unwrap();
+    assert_eq!(count, 3);
+}
+
+#[tokio::test]
+async fn test_async_transaction() {
+    let conn = setup_async_connection().await;
+
+    let result = conn
+        .transaction::<(), DieselError, _>(|conn| {
+            diesel::delete(users::table).execute(conn)?;
+            Err(DieselError::RollbackTransaction)
+        })
+        .await;
+    assert_eq!(result, Err(DieselError::RollbackTransaction));
+
+    let deleted = conn
+        .transaction::<_, DieselError, _>(|conn| {
+            diesel::delete(users::table.filter(users::id.eq(1))).execute(conn)
+        })
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+
+    let count: i64 = conn.get_result(users::table.count()).await.unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_async_stream() {
+    let conn = setup_async_connection().await;
+    conn.batch_execute(
+        "INSERT INTO users (id, name) SELECT i, 'user' || i FROM range(3, 201) t(i)",
+    )
+    .await
+    .unwrap();
+
+    let mut stream =
+        conn.load_buffered_stream::<_, i32>(users::table.select(users::id).order(users::id));
+    let mut ids = Vec::new();
+    while let Some(id) = next(&mut stream).await {
+        ids.push(id.unwrap());
+    }
+    assert_eq!(ids, (1..=200).collect::<Vec<_>>());
+
+    // Errors are yielded by the stream
+    let missing = diesel::dsl::sql::<diesel::sql_types::Integer>("SELECT missing FROM users");
+    let mut stream = conn.load_buffered_stream::<_, i32>(missing);
+    assert!(next(&mut stream).await.unwrap().is_err());
+    assert!(next(&mut stream).await.is_none());
+
+    // Dropping a stream early frees the worker
+    let mut stream = conn.load_buffered_stream::<_, i32>(users::table.select(users::id));
+    assert!(next(&mut stream).await.is_some());
+    drop(stream);
+    let count: i64 = conn.get_result(users::table.count()).await.unwrap();
+    assert_eq!(count, 200);
+}
+
+#[tokio::test]
+async fn test_async_from_connection_and_errors() {
+    assert!(AsyncDuckDbConnection::establish(":memory:?threads=lots")
+        .await
+        .is_err());
+
+    let mut sync_conn = DuckDbConnection::establish(":memory:").unwrap();
+    setup_users_table(&mut sync_conn);
+    let conn = AsyncDuckDbConnection::from_connection(sync_conn).unwrap();
+
+    let count: i64 = conn.get_result(users::table.count()).await.unwrap();
+    assert_eq!(count, 0);
+    assert!(conn.batch_execute("SELECT * FROM missing").await.is_err());
+
+    let missing = DuckDbConnection::builder("/nonexistent/dir/test.duckdb");
+    assert!(AsyncDuckDbConnection::from_builder(missing).await.is_err());
+}
+
+#[tokio::test]
+async fn test_async_worker_survives_panics() {
+    let conn = std::sync::Arc::new(setup_async_connection().await);
+
+    let panicking = conn.clone();
+    let result = tokio::spawn(async move {
+        panicking
+            .interact(|_| -> QueryResult<()> { panic!("boom") })
+            .await
+    })
+    .await;
+    assert!(result.unwrap_err().is_panic());
+
+    let count: i64 = conn.get_result(users::table.count()).await.unwrap();
+    assert_eq!(count, 2);
+}
@@ -0,0 +1,138 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+
+use super::schema::{archived_events, events, users};
+use super::{setup_basic_connection, setup_users_table, temp_database_path};
+use crate::{AttachOptions, CatalogTable, DuckDbConnection};
+
+fn create_analytics_database(name: &str) -> String {
+    let path = temp_database_path(name);
+    let path = path.to_str().unwrap().to_string();
+
+    let mut conn = DuckDbConnection::establish(&path).unwrap();
+    conn.batch_execute(
+        "
+        CREATE TABLE events (event_id INTEGER PRIMARY KEY, user_id INTEGER, kind VARCHAR);
+        INSERT INTO events VALUES (1, 1, 'login'), (2, 2, 'login'), (3, 1, 'logout');
+        CREATE SCHEMA archive;
+        CREATE TABLE archive.events (event_id INTEGER PRIMARY KEY, user_id INTEGER, kind VARCHAR);
+        INSERT INTO archive.events VALUES (100, 2, 'signup');
+        ",
+    )
+    .unwrap();
+    path
+}
+
+fn setup_main_database() -> DuckDbConnection {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    conn.batch_execute("INSERT INTO users (id, name) VALUES (1, 'Alice'), (2, 'Bob')")
+        .unwrap();
+    conn
+}
+
+#[test]
+fn test_attach_and_join_across_databases() {
+    let path = create_analytics_database("attach_join");
+    let mut conn = setup_main_database();
+    conn.attach(&path, "analytics", AttachOptions::new())
+        .unwrap();
+
+    let rows = events::table
+        .inner_join(users::table)
+        .select((events::kind, users::name))
+        .order(events::event_id)
+        .load::<(String, Option<String>)>(&mut conn)
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            ("login".to_string(), Some("Alice".to_string())),
+            ("login".to_string(), Some("Bob".to_string())),
+            ("logout".to_string(), Some("Alice".to_string())),
+        ]
+    );
+
+    // Tables outside the attached database's main schema
+    let kinds = archived_events::table
+        .in_catalog()
+        .select(archived_events::kind)
+        .load::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(kinds, vec!["signup"]);
+
+    let rows = archived_events::table
+        .in_catalog()
+        .inner_join(users::table.on(users::id.eq(archived_events::user_id)))
+        .select((archived_events::event_id, users::name))
+        .load::<(i32, Option<String>)>(&mut conn)
+        .unwrap();
+    assert_eq!(rows, vec![(100, Some("Bob".to_string()))]);
+
+    diesel::insert_into(events::table)
+        .values((
+            events::event_id.eq(4),
+            events::user_id.eq(2),
+            events::kind.eq("logout"),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    let count: i64 = events::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(count, 4);
+
+    conn.detach("analytics").unwrap();
+    assert!(events::table.count().get_result::<i64>(&mut conn).is_err());
+}
+
+#[test]
+fn test_attach_read_only() {
+    let path = create_analytics_database("attach_read_only");
+    let mut conn = setup_main_database();
+    conn.attach(&path, "analytics", AttachOptions::new().read_only())
+        .unwrap();
+
+    let count: i64 = events::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(count, 3);
+    assert!(diesel::delete(events::table).execute(&mut conn).is_err());
+}
+
+#[test]
+fn test_attach_quotes_path_and_alias() {
+    let path = temp_database_path("attach_quoting");
+    let path = path.with_file_name("it's.duckdb");
+    let mut conn = setup_basic_connection();
+
+    conn.attach(path.to_str().unwrap(), "odd \"name\"", AttachOptions::new())
+        .unwrap();
+    conn.batch_execute("CREATE TABLE \"odd \"\"name\"\"\".t (id INTEGER)")
+        .unwrap();
+    conn.detach("odd \"name\"").unwrap();
+    assert!(conn.detach("odd \"name\"").is_err());
+}
+
+diesel::table! {
+    #[sql_name = "dotted.users"]
+    dotted_users (id) {
+        id -> Integer,
+        #[sql_name = "user.name"]
+        name -> Text,
+    }
+}
+
+#[test]
+fn test_dotted_names_are_single_identifiers() {
+    let mut conn = setup_basic_connection();
+    conn.batch_execute("CREATE TABLE \"dotted.users\" (id INTEGER, \"user.name\" VARCHAR)")
+        .unwrap();
+
+    diesel::insert_into(dotted_users::table)
+        .values((dotted_users::id.eq(1), dotted_users::name.eq("Alice")))
+        .execute(&mut conn)
+        .unwrap();
+    let names = dotted_users::table
+        .filter(dotted_users::id.eq(1))
+        .select(dotted_users::name)
+        .load::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(names, vec!["Alice"]);
+}
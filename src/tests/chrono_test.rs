@@ -37,4 +37,24 @@ mod tests {
         // Test that the Queryable trait is available
         assert_eq!(std::any::type_name::<TestPrice>(), "diesel_duckdb::tests::chrono_test::TestPrice");
     }
+
+    #[test]
+    fn test_struct_loads() {
+        use diesel::connection::SimpleConnection;
+
+        let mut conn = crate::tests::setup_basic_connection();
+        conn.batch_execute(
+            "CREATE TABLE test_table (date TIMESTAMP, store_id BIGINT, price_cent BIGINT);
+             INSERT INTO test_table VALUES ('2022-01-01 00:00:00', 1, 100);",
+        )
+        .unwrap();
+
+        let test = test_table::table
+            .select(TestPrice::as_select())
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(test.date.date(), chrono::NaiveDate::from_ymd_opt(2022, 1, 1).unwrap());
+        assert_eq!(test.store_id, 1);
+        assert_eq!(test.price_cent, 100);
+    }
 }
@@ -0,0 +1,87 @@
+use super::temp_database_path;
+use crate::{AccessMode, DefaultOrder, DuckDbConnection, DuckDbConnectionBuilder, NullOrder};
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::ConnectionError;
+use diesel::sql_types::Text;
+
+fn setting(conn: &mut DuckDbConnection, name: &str) -> String {
+    diesel::select(sql::<Text>(&format!(
+        "CAST(current_setting('{}') AS VARCHAR)",
+        name
+    )))
+    .get_result(conn)
+    .unwrap()
+}
+
+#[test]
+fn test_builder_applies_typed_options() {
+    let mut conn = DuckDbConnection::builder(":memory:")
+        .threads(2)
+        .memory_limit("512MB")
+        .default_order(DefaultOrder::Desc)
+        .default_null_order(NullOrder::NullsLast)
+        .establish()
+        .unwrap();
+
+    assert_eq!(setting(&mut conn, "threads"), "2");
+    assert_eq!(setting(&mut conn, "default_order"), "desc");
+    assert_eq!(setting(&mut conn, "default_null_order"), "nulls_last");
+}
+
+#[test]
+fn test_url_parameters_are_parsed() {
+    let builder =
+        DuckDbConnectionBuilder::from_url("file.duckdb?threads=4&access_mode=read_only&enable_external_access=false&temp_directory=%2Ftmp%2Fspill")
+            .unwrap();
+
+    assert_eq!(
+        builder,
+        DuckDbConnectionBuilder::new("file.duckdb")
+            .threads(4)
+            .access_mode(AccessMode::ReadOnly)
+            .enable_external_access(false)
+            .temp_directory("/tmp/spill")
+    );
+}
+
+#[test]
+fn test_establish_uses_url_parameters() {
+    let mut conn = DuckDbConnection::establish(":memory:?threads=3&default_order=desc").unwrap();
+
+    assert_eq!(setting(&mut conn, "threads"), "3");
+    assert_eq!(setting(&mut conn, "default_order"), "desc");
+}
+
+#[test]
+fn test_invalid_url_parameters_are_rejected() {
+    assert!(matches!(
+        DuckDbConnection::establish(":memory:?thread=4"),
+        Err(ConnectionError::InvalidConnectionUrl(_))
+    ));
+    assert!(matches!(
+        DuckDbConnection::establish(":memory:?threads=many"),
+        Err(ConnectionError::InvalidConnectionUrl(_))
+    ));
+    assert!(matches!(
+        DuckDbConnection::establish(":memory:?access_mode=sometimes"),
+        Err(ConnectionError::InvalidConnectionUrl(_))
+    ));
+}
+
+#[test]
+fn test_read_only_access_mode_from_url() {
+    let path = temp_database_path("builder_read_only");
+    let path = path.to_str().unwrap();
+
+    let mut writer = DuckDbConnection::establish(path).unwrap();
+    writer
+        .batch_execute("CREATE TABLE t (id INTEGER); INSERT INTO t VALUES (1)")
+        .unwrap();
+    drop(writer);
+
+    let mut reader =
+        DuckDbConnection::establish(&format!("{}?access_mode=read_only", path)).unwrap();
+    assert!(reader.batch_execute("INSERT INTO t VALUES (2)").is_err());
+}
@@ -0,0 +1,434 @@
+// Feeds every kind of DuckDB value to every FromSql impl of the backend.
+// A conversion may fail, but it must never panic.
+use crate::{DuckDb, FromDuckDbValueError};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use diesel::deserialize::FromSql;
+use diesel::prelude::*;
+use diesel::sql_types;
+use duckdb::types::{OrderedMap, TimeUnit, ToSqlOutput, Value, ValueRef};
+
+fn sample_values() -> Vec<Value> {
+    vec![
+        Value::Null,
+        Value::Boolean(true),
+        Value::Boolean(false),
+        Value::TinyInt(i8::MIN),
+        Value::TinyInt(7),
+        Value::SmallInt(i16::MIN),
+        Value::SmallInt(i16::MAX),
+        Value::Int(i32::MIN),
+        Value::Int(42),
+        Value::BigInt(i64::MIN),
+        Value::BigInt(i64::MAX),
+        Value::HugeInt(i128::MIN),
+        Value::HugeInt(i128::MAX),
+        Value::HugeInt(300),
+        Value::UTinyInt(u8::MAX),
+        Value::USmallInt(u16::MAX),
+        Value::UInt(u32::MAX),
+        Value::UBigInt(u64::MAX),
+        Value::Float(1.5),
+        Value::Float(f32::NAN),
+        Value::Float(f32::NEG_INFINITY),
+        Value::Double(-2.25),
+        Value::Double(f64::NAN),
+        Value::Double(f64::MAX),
+        Value::Double(f64::INFINITY),
+        Value::Decimal("12.5".parse().unwrap()),
+        Value::Decimal("-79228162514264337593543950335".parse().unwrap()),
+        Value::Timestamp(TimeUnit::Second, 0),
+        Value::Timestamp(TimeUnit::Millisecond, -1),
+        Value::Timestamp(TimeUnit::Microsecond, i64::MAX),
+        Value::Timestamp(TimeUnit::Nanosecond, i64::MIN),
+        Value::Timestamp(TimeUnit::Second, i64::MIN),
+        Value::Text(String::new()),
+        Value::Text("x".to_string()),
+        Value::Text("12".to_string()),
+        Value::Text("2025-07-10".to_string()),
+        Value::Text("2025-07-10 12:30:45.5".to_string()),
+        Value::Text("12:30:45".to_string()),
+        Value::Text("ünïcödé".to_string()),
+        Value::Blob(vec![]),
+        Value::Blob(vec![0xff, 0x00, 0xfe]),
+        Value::Date32(0),
+        Value::Date32(i32::MIN),
+        Value::Date32(i32::MAX),
+        Value::Time64(TimeUnit::Microsecond, 45_045_000_000),
+        Value::Time64(TimeUnit::Microsecond, -1),
+        Value::Time64(TimeUnit::Second, i64::MAX),
+        Value::Time64(TimeUnit::Nanosecond, 86_400_000_000_000),
+        Value::Interval {
+            months: 1,
+            days: -2,
+            nanos: i64::MAX,
+        },
+        Value::List(vec![Value::Int(1), Value::Null]),
+        Value::List(vec![]),
+        Value::Enum("red".to_string()),
+        Value::Struct(OrderedMap::from(vec![(
+            "a".to_string(),
+            Value::Text("b".to_string()),
+        )])),
+        Value::Array(vec![Value::Double(1.0)]),
+        Value::Map(OrderedMap::from(vec![(Value::Int(1), Value::Boolean(true))])),
+        Value::Union(Box::new(Value::Int(1))),
+    ]
+}
+
+fn borrowed_samples() -> Vec<ValueRef<'static>> {
+    vec![
+        ValueRef::Null,
+        ValueRef::Int(-1),
+        ValueRef::HugeInt(i128::MAX),
+        ValueRef::Double(f64::NAN),
+        ValueRef::Text(b"2025-07-10"),
+        ValueRef::Text(&[0xff, 0xfe]),
+        ValueRef::Blob(&[1, 2, 3]),
+        ValueRef::Date32(i32::MIN),
+        ValueRef::Timestamp(TimeUnit::Microsecond, i64::MIN),
+        ValueRef::Time64(TimeUnit::Microsecond, i64::MAX),
+    ]
+}
+
+fn check_all<ST, T>()
+where
+    T: FromSql<ST, DuckDb> + std::fmt::Debug,
+{
+    for value in sample_values() {
+        let _ = T::from_sql(ToSqlOutput::Owned(value));
+    }
+    for value in borrowed_samples() {
+        let _ = T::from_sql(ToSqlOutput::Borrowed(value));
+    }
+}
+
+#[test]
+fn test_every_value_against_every_from_sql_impl() {
+    check_all::<sql_types::Text, String>();
+    check_all::<sql_types::Bool, bool>();
+    check_all::<sql_types::TinyInt, i8>();
+    check_all::<sql_types::SmallInt, i16>();
+    check_all::<sql_types::Integer, i32>();
+    check_all::<sql_types::BigInt, i64>();
+    check_all::<sql_types::Float, f32>();
+    check_all::<sql_types::Double, f64>();
+    check_all::<sql_types::Binary, Vec<u8>>();
+    check_all::<sql_types::Date, NaiveDate>();
+    check_all::<sql_types::Time, NaiveTime>();
+    check_all::<sql_types::Timestamp, NaiveDateTime>();
+}
+
+fn conversion_error<ST, T>(value: Value) -> FromDuckDbValueError
+where
+    T: FromSql<ST, DuckDb> + std::fmt::Debug,
+{
+    let err = T::from_sql(ToSqlOutput::Owned(value)).unwrap_err();
+    err.downcast_ref::<FromDuckDbValueError>()
+        .expect("conversion errors should be typed")
+        .clone()
+}
+
+#[test]
+fn test_integer_overflow_is_an_error() {
+    assert_eq!(
+        conversion_error::<sql_types::Integer, i32>(Value::BigInt(i64::MAX)),
+        FromDuckDbValueError::OutOfRange {
+            target: "i32",
+            value: i64::MAX.to_string(),
+        }
+    );
+    assert_eq!(
+        conversion_error::<sql_types::TinyInt, i8>(Value::HugeInt(300)),
+        FromDuckDbValueError::OutOfRange {
+            target: "i8",
+            value: "300".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_nested_values_are_invalid_types() {
+    assert_eq!(
+        conversion_error::<sql_types::Text, String>(Value::List(vec![Value::Int(1)])),
+        FromDuckDbValueError::InvalidType {
+            target: "String",
+            found: "LIST",
+        }
+    );
+    assert_eq!(
+        conversion_error::<sql_types::Integer, i32>(Value::Union(Box::new(Value::Int(1)))),
+        FromDuckDbValueError::InvalidType {
+            target: "i32",
+            found: "UNION",
+        }
+    );
+}
+
+#[test]
+fn test_out_of_range_temporal_values_are_errors() {
+    assert!(matches!(
+        conversion_error::<sql_types::Timestamp, NaiveDateTime>(Value::Timestamp(
+            TimeUnit::Second,
+            i64::MAX
+        )),
+        FromDuckDbValueError::OutOfRange { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Time, NaiveTime>(Value::Time64(TimeUnit::Microsecond, -1)),
+        FromDuckDbValueError::OutOfRange { .. }
+    ));
+}
+
+#[test]
+fn test_loading_overflowing_column_returns_error() {
+    let mut conn = super::setup_basic_connection();
+
+    let result = diesel::select(diesel::dsl::sql::<sql_types::Integer>(
+        "CAST(9223372036854775807 AS BIGINT)",
+    ))
+    .get_result::<i32>(&mut conn);
+
+    assert!(matches!(
+        result,
+        Err(diesel::result::Error::DeserializationError(_))
+    ));
+}
+
+#[test]
+fn test_loading_nested_column_returns_error() {
+    let mut conn = super::setup_basic_connection();
+
+    let result = diesel::select(diesel::dsl::sql::<sql_types::Text>("[1, 2, 3]"))
+        .get_result::<String>(&mut conn);
+
+    assert!(matches!(
+        result,
+        Err(diesel::result::Error::DeserializationError(_))
+    ));
+}
+
+fn converted<ST, T>(value: Value) -> T
+where
+    T: FromSql<ST, DuckDb> + std::fmt::Debug,
+{
+    T::from_sql(ToSqlOutput::Owned(value)).unwrap()
+}
+
+#[test]
+fn test_integer_widening() {
+    assert_eq!(converted::<sql_types::BigInt, i64>(Value::Int(-7)), -7);
+    assert_eq!(converted::<sql_types::BigInt, i64>(Value::UInt(u32::MAX)), 4_294_967_295);
+    assert_eq!(converted::<sql_types::Integer, i32>(Value::TinyInt(-3)), -3);
+    assert_eq!(converted::<sql_types::BigInt, i64>(Value::HugeInt(1 << 40)), 1 << 40);
+    assert_eq!(
+        converted::<sql_types::BigInt, i64>(Value::Decimal("1200.00".parse().unwrap())),
+        1200
+    );
+}
+
+#[test]
+fn test_integer_narrowing_errors() {
+    assert!(matches!(
+        conversion_error::<sql_types::BigInt, i64>(Value::UBigInt(u64::MAX)),
+        FromDuckDbValueError::OutOfRange { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::BigInt, i64>(Value::Decimal("12.5".parse().unwrap())),
+        FromDuckDbValueError::Invalid { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Integer, i32>(Value::Double(1.0)),
+        FromDuckDbValueError::InvalidType { .. }
+    ));
+}
+
+#[test]
+fn test_float_widening() {
+    assert_eq!(converted::<sql_types::Double, f64>(Value::Float(1.5)), 1.5);
+    assert_eq!(converted::<sql_types::Double, f64>(Value::BigInt(42)), 42.0);
+    assert_eq!(
+        converted::<sql_types::Double, f64>(Value::Decimal("1299.99".parse().unwrap())),
+        1299.99
+    );
+    assert_eq!(converted::<sql_types::Float, f32>(Value::SmallInt(-12)), -12.0);
+    assert_eq!(
+        converted::<sql_types::Float, f32>(Value::Decimal("0.1".parse().unwrap())),
+        0.1
+    );
+}
+
+#[test]
+fn test_decimals_must_round_trip_through_floats() {
+    // 19 significant digits, more than a double carries
+    let precise = Value::Decimal("1234567.890123456789".parse().unwrap());
+    assert_eq!(
+        conversion_error::<sql_types::Double, f64>(precise.clone()),
+        FromDuckDbValueError::Invalid {
+            target: "f64",
+            message: "1234567.890123456789 doesn't round-trip through f64".to_string(),
+        }
+    );
+    assert!(matches!(
+        conversion_error::<sql_types::Float, f32>(precise),
+        FromDuckDbValueError::Invalid { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Float, f32>(Value::Decimal("1234.56789".parse().unwrap())),
+        FromDuckDbValueError::Invalid { .. }
+    ));
+    assert_eq!(
+        converted::<sql_types::Float, f32>(Value::Decimal("1299.5".parse().unwrap())),
+        1299.5
+    );
+}
+
+#[test]
+fn test_float_narrowing_errors() {
+    assert!(matches!(
+        conversion_error::<sql_types::Double, f64>(Value::BigInt((1 << 53) + 1)),
+        FromDuckDbValueError::OutOfRange { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Float, f32>(Value::Int((1 << 24) + 1)),
+        FromDuckDbValueError::OutOfRange { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Float, f32>(Value::Double(0.1)),
+        FromDuckDbValueError::InvalidType { .. }
+    ));
+}
+
+#[test]
+fn test_lossy_conversions_are_invalid_types() {
+    assert_eq!(
+        conversion_error::<sql_types::Bool, bool>(Value::Int(2)),
+        FromDuckDbValueError::InvalidType {
+            target: "bool",
+            found: "INTEGER",
+        }
+    );
+    assert_eq!(
+        conversion_error::<sql_types::Integer, i32>(Value::Text("12".to_string())),
+        FromDuckDbValueError::InvalidType {
+            target: "i32",
+            found: "VARCHAR",
+        }
+    );
+    assert!(matches!(
+        conversion_error::<sql_types::Double, f64>(Value::Text("1.5".to_string())),
+        FromDuckDbValueError::InvalidType { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Date, NaiveDate>(Value::Text("2025-07-10".to_string())),
+        FromDuckDbValueError::InvalidType { .. }
+    ));
+    assert!(matches!(
+        conversion_error::<sql_types::Binary, Vec<u8>>(Value::Text("x".to_string())),
+        FromDuckDbValueError::InvalidType { .. }
+    ));
+}
+
+#[test]
+fn test_date_widens_to_timestamp() {
+    let expected = NaiveDate::from_ymd_opt(2025, 7, 10)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let days = (expected.date() - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+
+    assert_eq!(
+        converted::<sql_types::Timestamp, NaiveDateTime>(Value::Date32(days as i32)),
+        expected
+    );
+}
+
+#[test]
+fn test_aggregate_results_deserialize_into_natural_types() {
+    use super::schema::{orders, users};
+    use diesel::dsl::{count_star, sql};
+
+    let mut conn = super::setup_orders_with_sample_data();
+
+    // COUNT(*) is a BIGINT
+    let user_count = users::table.select(count_star()).get_result::<i64>(&mut conn);
+    assert_eq!(user_count, Ok(2));
+
+    // SUM over INTEGER is a HUGEINT
+    let total_quantity = orders::table
+        .select(sql::<sql_types::Nullable<sql_types::BigInt>>("SUM(quantity)"))
+        .get_result::<Option<i64>>(&mut conn);
+    assert_eq!(total_quantity, Ok(Some(4)));
+
+    // SUM over DECIMAL is a DECIMAL(38, s)
+    let total_price = orders::table
+        .select(sql::<sql_types::Double>("SUM(CAST(price AS DECIMAL(10, 2)))"))
+        .get_result::<f64>(&mut conn);
+    assert_eq!(total_price, Ok(1100.49));
+
+    let max_price = orders::table
+        .select(sql::<sql_types::Nullable<sql_types::Double>>("MAX(price)"))
+        .get_result::<Option<f64>>(&mut conn);
+    assert_eq!(max_price, Ok(Some(999.99)));
+
+    let small = diesel::select(sql::<sql_types::BigInt>("CAST(5 AS TINYINT)"))
+        .get_result::<i64>(&mut conn);
+    assert_eq!(small, Ok(5));
+}
+
+// Returns text that isn't valid UTF-8, which DuckDB rejects anywhere else
+#[cfg(feature = "vscalar")]
+struct InvalidUtf8;
+
+#[cfg(feature = "vscalar")]
+impl duckdb::vscalar::VScalar for InvalidUtf8 {
+    type State = ();
+
+    unsafe fn invoke(
+        _: &(),
+        input: &mut duckdb::core::DataChunkHandle,
+        output: &mut dyn duckdb::vtab::arrow::WritableVector,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        use duckdb::core::Inserter;
+
+        let output = output.flat_vector();
+        for row in 0..input.len() {
+            output.insert(row, b"caf\xe9".as_slice());
+        }
+        Ok(())
+    }
+
+    fn signatures() -> Vec<duckdb::vscalar::ScalarFunctionSignature> {
+        use duckdb::core::{LogicalTypeHandle, LogicalTypeId};
+
+        vec![duckdb::vscalar::ScalarFunctionSignature::exact(
+            vec![LogicalTypeHandle::from(LogicalTypeId::Bigint)],
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+        )]
+    }
+}
+
+#[cfg(feature = "vscalar")]
+#[test]
+fn test_loading_invalid_utf8_returns_error() {
+    let mut conn = super::setup_basic_connection();
+    conn.as_ref()
+        .register_scalar_function::<InvalidUtf8>("invalid_utf8")
+        .unwrap();
+
+    // from a column, a constant argument would be folded into a checked value
+    let result = diesel::select(diesel::dsl::sql::<sql_types::Text>(
+        "(SELECT invalid_utf8(i) FROM range(1) t(i))",
+    ))
+    .get_result::<String>(&mut conn);
+
+    let Err(diesel::result::Error::DeserializationError(error)) = result else {
+        panic!("expected a deserialization error, got {:?}", result);
+    };
+    let error = error
+        .downcast_ref::<diesel::result::DeserializeFieldError>()
+        .unwrap();
+    assert!(matches!(
+        error.error.downcast_ref::<FromDuckDbValueError>(),
+        Some(FromDuckDbValueError::Invalid { target: "text", .. })
+    ));
+}
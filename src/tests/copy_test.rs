@@ -0,0 +1,174 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+
+use super::schema::users;
+use super::{
+    setup_basic_connection, setup_users_table, setup_users_with_extended_data, temp_database_path,
+    User,
+};
+use crate::{CopyCompression, CopyFormat, CopyOptions, DuckDbConnection};
+
+fn copy_path(test: &str, file: &str) -> String {
+    let path = temp_database_path(test);
+    path.parent()
+        .unwrap()
+        .join(file)
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+fn user_names(conn: &mut DuckDbConnection) -> Vec<Option<String>> {
+    users::table
+        .order(users::id)
+        .select(users::name)
+        .load(conn)
+        .unwrap()
+}
+
+fn round_trip(test: &str, file: &str, format: CopyFormat, options: CopyOptions) {
+    let path = copy_path(test, file);
+    let mut source = setup_users_with_extended_data();
+
+    let query = users::table.filter(users::age.eq(30)).order(users::id);
+    let copied = source.copy_to(query, &path, format, &options).unwrap();
+    assert_eq!(copied, 2);
+
+    let mut target = setup_basic_connection();
+    setup_users_table(&mut target);
+    let loaded = target
+        .copy_from(users::table, &path, format, &options)
+        .unwrap();
+    assert_eq!(loaded, 2);
+    assert_eq!(
+        user_names(&mut target),
+        [
+            Some("John Doe".to_string()),
+            Some("Alice Brown".to_string())
+        ]
+    );
+
+    let users = users::table
+        .select(User::as_select())
+        .load(&mut target)
+        .unwrap();
+    assert!(users.iter().all(|user| user.age == Some(30)));
+}
+
+#[test]
+fn test_copy_csv() {
+    round_trip(
+        "copy_csv",
+        "users.csv",
+        CopyFormat::Csv,
+        CopyOptions::new().header(true).delimiter('|'),
+    );
+}
+
+#[test]
+fn test_copy_compressed_csv() {
+    round_trip(
+        "copy_csv_gzip",
+        "users.csv.gz",
+        CopyFormat::Csv,
+        CopyOptions::new().compression(CopyCompression::Gzip),
+    );
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_copy_parquet() {
+    round_trip(
+        "copy_parquet",
+        "users.parquet",
+        CopyFormat::Parquet,
+        CopyOptions::new()
+            .compression(CopyCompression::Zstd)
+            .row_group_size(1024),
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_copy_json() {
+    round_trip(
+        "copy_json",
+        "users.json",
+        CopyFormat::Json,
+        CopyOptions::new(),
+    );
+}
+
+#[test]
+fn test_copy_csv_header_and_delimiter() {
+    let path = copy_path("copy_header", "names.csv");
+    let mut conn = setup_users_with_extended_data();
+
+    let query = users::table
+        .select((users::id, users::name))
+        .filter(users::name.like("J%"))
+        .order(users::id);
+    conn.copy_to(
+        query,
+        &path,
+        CopyFormat::Csv,
+        &CopyOptions::new().header(true).delimiter(';'),
+    )
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "id;name\n1;John Doe\n2;Jane Smith\n"
+    );
+}
+
+#[test]
+fn test_copy_partitioned() {
+    let dir = copy_path("copy_partitioned", "by_age");
+    let mut conn = setup_users_with_extended_data();
+
+    let copied = conn
+        .copy_to(
+            users::table.select((users::id, users::age)),
+            &dir,
+            CopyFormat::Csv,
+            &CopyOptions::new().partition_by(["age"]),
+        )
+        .unwrap();
+    assert_eq!(copied, 4);
+
+    let mut partitions = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    partitions.sort();
+    assert_eq!(partitions, ["age=25", "age=30", "age=35"]);
+}
+
+#[test]
+fn test_copy_errors() {
+    let path = copy_path("copy_errors", "missing.csv");
+    let mut conn = setup_users_with_extended_data();
+
+    let result = conn.copy_from(users::table, &path, CopyFormat::Csv, &CopyOptions::new());
+    assert!(result.is_err());
+
+    // row groups only exist in Parquet files
+    let result = conn.copy_to(
+        users::table,
+        &path,
+        CopyFormat::Csv,
+        &CopyOptions::new().row_group_size(10),
+    );
+    assert!(result.is_err());
+
+    // paths are quoted, not interpolated
+    let path = copy_path("copy_errors", "it's.csv");
+    conn.copy_to(users::table, &path, CopyFormat::Csv, &CopyOptions::new())
+        .unwrap();
+    conn.batch_execute("DELETE FROM users").unwrap();
+    let loaded = conn
+        .copy_from(users::table, &path, CopyFormat::Csv, &CopyOptions::new())
+        .unwrap();
+    assert_eq!(loaded, 4);
+}
@@ -0,0 +1,258 @@
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::Bool;
+
+use super::schema::users;
+use super::{setup_basic_connection, setup_users_table};
+use crate::{DuckDb, DuckDbConnection, DuckDbErrorExt, DuckDbErrorInformation, DuckDbErrorKind};
+
+fn setup_constraints() -> DuckDbConnection {
+    let mut conn = setup_basic_connection();
+    conn.batch_execute(
+        "
+        CREATE TABLE parents (id INTEGER PRIMARY KEY, name VARCHAR NOT NULL, code INTEGER UNIQUE, age INTEGER CHECK (age >= 0));
+        CREATE TABLE children (id INTEGER, parent_id INTEGER REFERENCES parents(id));
+        INSERT INTO parents VALUES (1, 'Alice', 1, 30);
+        INSERT INTO children VALUES (1, 1);
+        ",
+    )
+    .unwrap();
+    conn
+}
+
+// The diesel and DuckDB kinds of the error `sql` fails with
+// (`DatabaseErrorKind` has no `PartialEq`, so compare it through `Debug`)
+fn error_kinds(conn: &mut DuckDbConnection, sql: &str) -> (DatabaseErrorKind, DuckDbErrorKind) {
+    let error = conn.batch_execute(sql).unwrap_err();
+    let duckdb_kind = error.duckdb_error_kind().unwrap();
+    match error {
+        DieselError::DatabaseError(kind, _) => (kind, duckdb_kind),
+        other => panic!("expected a database error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_constraint_errors() {
+    let mut conn = setup_constraints();
+    let cases = [
+        (
+            "INSERT INTO parents VALUES (1, 'Bob', 2, 1)",
+            DatabaseErrorKind::UniqueViolation,
+        ),
+        (
+            "INSERT INTO parents VALUES (2, 'Bob', 1, 1)",
+            DatabaseErrorKind::UniqueViolation,
+        ),
+        (
+            "INSERT INTO parents SELECT 5, 'Eve', 5, 1 FROM range(2)",
+            DatabaseErrorKind::UniqueViolation,
+        ),
+        (
+            "INSERT INTO parents VALUES (3, NULL, 3, 1)",
+            DatabaseErrorKind::NotNullViolation,
+        ),
+        (
+            "INSERT INTO parents VALUES (4, 'Dan', 4, -1)",
+            DatabaseErrorKind::CheckViolation,
+        ),
+        (
+            "INSERT INTO children VALUES (2, 99)",
+            DatabaseErrorKind::ForeignKeyViolation,
+        ),
+        (
+            "DELETE FROM parents WHERE id = 1",
+            DatabaseErrorKind::ForeignKeyViolation,
+        ),
+    ];
+
+    for (sql, expected) in cases {
+        let (kind, duckdb_kind) = error_kinds(&mut conn, sql);
+        assert_eq!(duckdb_kind, DuckDbErrorKind::Constraint, "{}", sql);
+        assert_eq!(format!("{:?}", kind), format!("{:?}", expected), "{}", sql);
+    }
+}
+
+#[test]
+fn test_statement_errors() {
+    let mut conn = setup_constraints();
+    let cases = [
+        ("SELECT * FROM missing", DuckDbErrorKind::Catalog),
+        ("SELECT nme FROM parents", DuckDbErrorKind::Binder),
+        ("SELEC 1", DuckDbErrorKind::Parser),
+        ("SELECT 'abc'::INTEGER", DuckDbErrorKind::Conversion),
+        (
+            "SELECT 2147483647::INTEGER + 1",
+            DuckDbErrorKind::OutOfRange,
+        ),
+        ("COMMIT", DuckDbErrorKind::Transaction),
+        (
+            "SELECT * FROM read_csv('/nonexistent/file.csv')",
+            DuckDbErrorKind::Io,
+        ),
+    ];
+
+    for (sql, expected) in cases {
+        let (kind, duckdb_kind) = error_kinds(&mut conn, sql);
+        assert_eq!(duckdb_kind, expected, "{}", sql);
+        assert!(matches!(kind, DatabaseErrorKind::Unknown), "{}", sql);
+    }
+}
+
+#[test]
+fn test_kind_from_message() {
+    let cases = [
+        (
+            "Constraint Error: Duplicate key",
+            DuckDbErrorKind::Constraint,
+        ),
+        (
+            "TransactionContext Error: cannot commit",
+            DuckDbErrorKind::Transaction,
+        ),
+        (
+            "Invalid Input Error: read-only mode",
+            DuckDbErrorKind::InvalidInput,
+        ),
+        ("INTERRUPT Error: Interrupted!", DuckDbErrorKind::Interrupt),
+        (
+            "Out of Memory Error: failed to allocate",
+            DuckDbErrorKind::OutOfMemory,
+        ),
+        ("Unheard Of Error: something", DuckDbErrorKind::Other),
+        // Timeouts are told by the error, not the message
+        ("TIMEOUT Error: Query timed out", DuckDbErrorKind::Other),
+        ("DuckDB error code: 1", DuckDbErrorKind::Other),
+        ("Binder Error", DuckDbErrorKind::Other),
+    ];
+
+    for (message, expected) in cases {
+        assert_eq!(
+            DuckDbErrorKind::from_message(message),
+            expected,
+            "{}",
+            message
+        );
+        let info = DuckDbErrorInformation::from_message(message.to_string());
+        assert_eq!(info.kind(), expected, "{}", message);
+    }
+}
+
+#[test]
+fn test_non_database_errors_have_no_kind() {
+    assert_eq!(DieselError::NotFound.duckdb_error_kind(), None);
+}
+
+fn error_information(
+    result: QueryResult<impl std::fmt::Debug>,
+) -> Box<dyn DatabaseErrorInformation + Send + Sync> {
+    match result {
+        Err(DieselError::DatabaseError(_, info)) => info,
+        other => panic!("expected a database error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_statement_position_of_query() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+
+    let query = users::table
+        .select(users::id)
+        .filter(sql::<Bool>("nme = 'Alice'"));
+    let sql = diesel::debug_query::<DuckDb, _>(&query).to_string();
+    let info = error_information(query.load::<i32>(&mut conn));
+
+    assert_eq!(
+        info.statement_position(),
+        Some(sql.find("nme").unwrap() as i32 + 1)
+    );
+    assert!(info.hint().unwrap().contains("\"name\""));
+    assert_eq!(info.details(), None);
+    // the full message is kept
+    assert!(info.message().contains("LINE 1:"));
+}
+
+#[test]
+fn test_statement_position_in_long_line() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+
+    let columns = (0..40)
+        .map(|i| format!("{} AS c{}", i, i))
+        .collect::<Vec<_>>();
+    let sql = format!("SELECT {}, nme, email FROM users", columns.join(", "));
+    let info = error_information(conn.batch_execute(&sql));
+
+    assert!(info.message().contains("LINE 1: ..."));
+    assert_eq!(
+        info.statement_position(),
+        Some(sql.find("nme").unwrap() as i32 + 1)
+    );
+}
+
+#[test]
+fn test_statement_position_in_later_line() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+
+    let sql = "SELECT id\nFROM userz\nWHERE id > 1";
+    let info = error_information(conn.batch_execute(sql));
+
+    assert_eq!(
+        info.statement_position(),
+        Some(sql.find("userz").unwrap() as i32 + 1)
+    );
+    assert_eq!(info.hint(), Some("Did you mean \"users\"?"));
+}
+
+#[test]
+fn test_statement_position_after_non_ascii_identifier() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+
+    let sql = "SELECT id AS \"prénom_名前\", nme FROM users";
+    let info = error_information(conn.batch_execute(sql));
+
+    // Counted in chars, not bytes or the two columns DuckDB shows `名` in
+    let before = &sql[..sql.find("nme").unwrap()];
+    assert_eq!(
+        info.statement_position(),
+        Some(before.chars().count() as i32 + 1)
+    );
+}
+
+#[test]
+fn test_error_details() {
+    let mut conn = setup_basic_connection();
+
+    let info = error_information(conn.batch_execute("SELECT 'x' + 1"));
+    let details = info.details().unwrap();
+    assert!(details.starts_with("Candidate functions:"));
+    assert!(details.contains("+(INTEGER, INTEGER) -> INTEGER"));
+    assert_eq!(info.hint(), None);
+}
+
+#[test]
+fn test_statement_position_of_unexpected_context() {
+    use crate::error::MapQueryError;
+
+    let message = "Parser Error: syntax error\n\nLINE 0: SELECT\n        ^";
+    let error = Err::<(), _>(duckdb::Error::DuckDBFailure(
+        duckdb::ffi::Error::new(duckdb::ffi::DuckDBError),
+        Some(message.to_string()),
+    ))
+    .map_query_error("SELECT");
+    assert_eq!(error_information(error).statement_position(), None);
+}
+
+#[test]
+fn test_errors_without_context() {
+    let mut conn = setup_constraints();
+
+    let info = error_information(conn.batch_execute("INSERT INTO parents VALUES (1, 'Bob', 2, 1)"));
+    assert_eq!(info.statement_position(), None);
+    assert_eq!(info.details(), None);
+    assert_eq!(info.hint(), None);
+}
@@ -0,0 +1,56 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+
+use super::schema::users;
+use super::{setup_basic_connection, setup_users_table};
+use crate::DuckDbConnection;
+
+fn setup_users() -> DuckDbConnection {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    conn.batch_execute(
+        "INSERT INTO users (id, name, age) SELECT range, 'user', range % 50 FROM range(100)",
+    )
+    .unwrap();
+    conn
+}
+
+#[test]
+fn test_explain() {
+    let mut conn = setup_users();
+    let query = users::table.select(users::name).filter(users::age.gt(40));
+
+    let plan = conn.explain(&query).unwrap();
+    let scan = plan
+        .nodes()
+        .find(|node| node.operator == "SEQ_SCAN")
+        .expect("plan has a table scan");
+    assert_eq!(scan.details["Table"], "users");
+    assert!(scan.details["Filters"].contains("age>40"));
+    assert!(scan.estimated_cardinality.is_some());
+    assert!(plan
+        .nodes()
+        .all(|node| node.cardinality.is_none() && node.timing.is_none()));
+}
+
+#[test]
+fn test_explain_analyze() {
+    let mut conn = setup_users();
+    let query = users::table.select(users::name).filter(users::age.gt(40));
+
+    let plan = conn.explain_analyze(&query).unwrap();
+    assert_ne!(plan.operator, "EXPLAIN_ANALYZE");
+    let scan = plan
+        .nodes()
+        .find(|node| node.operator == "SEQ_SCAN")
+        .expect("plan has a table scan");
+    assert_eq!(scan.cardinality, Some(18));
+    assert_eq!(scan.rows_scanned, Some(100));
+    assert!(plan.nodes().all(|node| node.timing.is_some()));
+}
+
+#[test]
+fn test_explain_invalid_query() {
+    let mut conn = setup_basic_connection();
+    assert!(conn.explain(&users::table.select(users::id)).is_err());
+}
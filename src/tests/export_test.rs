@@ -0,0 +1,103 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+
+use super::schema::{orders, users};
+use super::{
+    setup_basic_connection, setup_orders_with_sample_data, temp_database_path, Order, User,
+};
+use crate::{DuckDbConnection, ExportFormat};
+
+fn export_dir(name: &str) -> String {
+    let path = temp_database_path(name);
+    path.parent()
+        .unwrap()
+        .join("export")
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+// Debug output stands in for equality, the fixtures don't implement PartialEq
+fn contents(conn: &mut DuckDbConnection) -> (String, String) {
+    let users = users::table
+        .order(users::id)
+        .select(User::as_select())
+        .load(conn)
+        .unwrap();
+    let orders = orders::table
+        .order(orders::order_id)
+        .select(Order::as_select())
+        .load(conn)
+        .unwrap();
+    (format!("{:?}", users), format!("{:?}", orders))
+}
+
+fn round_trip(name: &str, format: ExportFormat) {
+    let dir = export_dir(name);
+    let mut source = setup_orders_with_sample_data();
+    source
+        .batch_execute("CREATE VIEW big_orders AS SELECT * FROM orders WHERE price > 50")
+        .unwrap();
+
+    let manifest = source.export_database(&dir, format).unwrap();
+    let mut tables = manifest
+        .tables
+        .iter()
+        .map(|table| (table.schema.as_str(), table.name.as_str()))
+        .collect::<Vec<_>>();
+    tables.sort();
+    assert_eq!(tables, [("main", "orders"), ("main", "users")]);
+    assert!(manifest.tables.iter().all(|table| table.path.is_file()));
+
+    let mut target = setup_basic_connection();
+    target.import_database(&dir).unwrap();
+    assert_eq!(contents(&mut target), contents(&mut source));
+
+    let big_orders =
+        diesel::dsl::sql::<diesel::sql_types::BigInt>("(SELECT count(*) FROM big_orders)");
+    assert_eq!(
+        diesel::select(big_orders).get_result::<i64>(&mut target),
+        Ok(2)
+    );
+}
+
+#[test]
+fn test_export_import_csv() {
+    round_trip("export_csv", ExportFormat::Csv);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_export_import_parquet() {
+    round_trip(
+        "export_parquet",
+        ExportFormat::Parquet {
+            compression: crate::ParquetCompression::Zstd,
+        },
+    );
+}
+
+#[test]
+fn test_manifest_of_quoted_names() {
+    let dir = export_dir("export_quoted");
+    let mut conn = setup_basic_connection();
+    conn.batch_execute(
+        "CREATE SCHEMA \"it's\"; CREATE TABLE \"it's\".\"odd \"\"name\"\"\" (id INTEGER)",
+    )
+    .unwrap();
+
+    let manifest = conn.export_database(&dir, ExportFormat::default()).unwrap();
+    assert_eq!(manifest.tables.len(), 1);
+    assert_eq!(manifest.tables[0].schema, "it's");
+    assert_eq!(manifest.tables[0].name, "odd \"name\"");
+    assert!(manifest.tables[0].path.is_file());
+}
+
+#[test]
+fn test_import_into_non_empty_database() {
+    let dir = export_dir("export_conflict");
+    let mut conn = setup_orders_with_sample_data();
+    conn.export_database(&dir, ExportFormat::Csv).unwrap();
+
+    assert!(conn.import_database(&dir).is_err());
+}
@@ -0,0 +1,189 @@
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use diesel::connection::{
+    set_default_instrumentation, Instrumentation, InstrumentationEvent, SimpleConnection,
+};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use super::schema::users;
+use super::{setup_basic_connection, setup_users_table};
+use crate::DuckDbConnection;
+
+fn describe(event: InstrumentationEvent<'_>) -> String {
+    match event {
+        InstrumentationEvent::StartEstablishConnection { url, .. } => format!("establish {}", url),
+        InstrumentationEvent::FinishEstablishConnection { url, error, .. } => {
+            format!("established {} ok={}", url, error.is_none())
+        }
+        InstrumentationEvent::StartQuery { query, .. } => format!("start {}", query),
+        InstrumentationEvent::CacheQuery { sql, .. } => format!("cache {}", sql),
+        InstrumentationEvent::FinishQuery { query, error, .. } => {
+            format!("finish {} ok={}", query, error.is_none())
+        }
+        InstrumentationEvent::BeginTransaction { depth, .. } => format!("begin {}", depth),
+        InstrumentationEvent::CommitTransaction { depth, .. } => format!("commit {}", depth),
+        InstrumentationEvent::RollbackTransaction { depth, .. } => format!("rollback {}", depth),
+        _ => "other".to_string(),
+    }
+}
+
+fn record_events(conn: &mut DuckDbConnection) -> Arc<Mutex<Vec<String>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    conn.set_instrumentation(move |event: InstrumentationEvent<'_>| {
+        recorded.lock().unwrap().push(describe(event));
+    });
+    events
+}
+
+fn take(events: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+    std::mem::take(&mut *events.lock().unwrap())
+}
+
+#[test]
+fn test_query_events() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    let events = record_events(&mut conn);
+
+    let query = users::table.select(users::id).filter(users::id.eq(1));
+    query.load::<i32>(&mut conn).unwrap();
+    let sql = r#"SELECT "users"."id" FROM "users" WHERE ("users"."id" = ?)"#;
+    assert_eq!(
+        take(&events),
+        vec![
+            format!("start {} -- binds: [1]", sql),
+            format!("cache {}", sql),
+            format!("finish {} -- binds: [1] ok=true", sql),
+        ]
+    );
+
+    // The second run hits the statement cache
+    query.load::<i32>(&mut conn).unwrap();
+    assert_eq!(
+        take(&events),
+        vec![
+            format!("start {} -- binds: [1]", sql),
+            format!("finish {} -- binds: [1] ok=true", sql),
+        ]
+    );
+
+    diesel::delete(users::table).execute(&mut conn).unwrap();
+    let sql = r#"DELETE FROM "users""#;
+    assert_eq!(
+        take(&events),
+        vec![
+            format!("start {} -- binds: []", sql),
+            format!("cache {}", sql),
+            format!("finish {} -- binds: [] ok=true", sql),
+        ]
+    );
+
+    conn.batch_execute("SELECT * FROM missing").unwrap_err();
+    assert_eq!(
+        take(&events),
+        vec![
+            "start SELECT * FROM missing".to_string(),
+            "finish SELECT * FROM missing ok=false".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_failed_query_events() {
+    let mut conn = setup_basic_connection();
+    let events = record_events(&mut conn);
+
+    users::table
+        .count()
+        .get_result::<i64>(&mut conn)
+        .unwrap_err();
+    let events = take(&events);
+    assert_eq!(events.len(), 3);
+    assert!(events[0].starts_with("start SELECT COUNT(*)"));
+    assert!(events[1].starts_with("cache SELECT COUNT(*)"));
+    assert!(events[2].ends_with("ok=false"));
+}
+
+#[test]
+fn test_transaction_events() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    let events = record_events(&mut conn);
+
+    conn.transaction::<_, DieselError, _>(|_| Ok(())).unwrap();
+    assert_eq!(
+        take(&events),
+        vec![
+            "begin 1",
+            "start BEGIN",
+            "finish BEGIN ok=true",
+            "commit 1",
+            "start COMMIT",
+            "finish COMMIT ok=true",
+        ]
+    );
+
+    conn.transaction::<(), _, _>(|_| Err(DieselError::RollbackTransaction))
+        .unwrap_err();
+    assert_eq!(
+        take(&events),
+        vec![
+            "begin 1",
+            "start BEGIN",
+            "finish BEGIN ok=true",
+            "rollback 1",
+            "start ROLLBACK",
+            "finish ROLLBACK ok=true",
+        ]
+    );
+}
+
+thread_local! {
+    static ESTABLISH_EVENTS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+// Records into `ESTABLISH_EVENTS` for connections established on threads that enabled it
+fn thread_local_instrumentation() -> Option<Box<dyn Instrumentation>> {
+    let enabled = ESTABLISH_EVENTS.with(|events| events.borrow().is_some());
+    if !enabled {
+        return None;
+    }
+    Some(Box::new(|event: InstrumentationEvent<'_>| {
+        let event = describe(event);
+        ESTABLISH_EVENTS.with(|events| events.borrow_mut().as_mut().map(|e| e.push(event)));
+    }))
+}
+
+#[test]
+fn test_establish_events() {
+    set_default_instrumentation(thread_local_instrumentation).unwrap();
+    ESTABLISH_EVENTS.with(|events| *events.borrow_mut() = Some(Vec::new()));
+
+    let mut conn = DuckDbConnection::establish(":memory:?threads=1").unwrap();
+    assert!(DuckDbConnection::establish(":memory:?threads=none").is_err());
+    assert!(DuckDbConnection::establish_with_flags(
+        "/nonexistent/dir/test.duckdb",
+        Default::default()
+    )
+    .is_err());
+    // The connection keeps the instrumentation that saw it being established
+    conn.batch_execute("SELECT 1").unwrap();
+
+    let events = ESTABLISH_EVENTS.with(|events| events.borrow_mut().take().unwrap());
+    assert_eq!(
+        events,
+        vec![
+            "establish :memory:?threads=1",
+            "established :memory:?threads=1 ok=true",
+            "establish :memory:?threads=none",
+            "established :memory:?threads=none ok=false",
+            "establish /nonexistent/dir/test.duckdb",
+            "established /nonexistent/dir/test.duckdb ok=false",
+            "start SELECT 1",
+            "finish SELECT 1 ok=true",
+        ]
+    );
+}
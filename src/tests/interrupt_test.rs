@@ -0,0 +1,102 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+
+use super::setup_basic_connection;
+use crate::{DuckDbConnection, DuckDbConnectionBuilder, DuckDbErrorExt, DuckDbErrorKind};
+
+// Finishes on its own, but long after any test interrupts it
+const SLOW_QUERY: &str = "SELECT count(*) FROM range(10000000000) t(i) WHERE i % 7 = 3";
+
+fn slow_query(conn: &mut DuckDbConnection) -> QueryResult<i64> {
+    diesel::select(sql::<BigInt>(&format!("({})", SLOW_QUERY))).get_result(conn)
+}
+
+#[test]
+fn test_interrupt_handle() {
+    let mut conn = setup_basic_connection();
+    let handle = conn.interrupt_handle();
+
+    let (started, start) = mpsc::channel();
+    let (finished, finish) = mpsc::channel::<()>();
+
+    // Only a running query sees the interrupt, so keep interrupting until it ends
+    let interrupter = std::thread::spawn(move || {
+        start.recv().unwrap();
+        while finish.recv_timeout(Duration::from_millis(10)) == Err(RecvTimeoutError::Timeout) {
+            handle.interrupt();
+        }
+    });
+    started.send(()).unwrap();
+    let result = slow_query(&mut conn);
+    finished.send(()).unwrap();
+    interrupter.join().unwrap();
+
+    let error = result.expect_err("query was interrupted");
+    assert!(error.is_interrupted());
+    assert!(!error.is_timeout());
+    assert_eq!(error.duckdb_error_kind(), Some(DuckDbErrorKind::Interrupt));
+
+    let one = diesel::select(sql::<BigInt>("1")).get_result::<i64>(&mut conn);
+    assert_eq!(one, Ok(1));
+}
+
+#[test]
+fn test_query_timeout() {
+    let mut conn = setup_basic_connection();
+    conn.set_query_timeout(Some(Duration::from_millis(100)));
+
+    let error = slow_query(&mut conn).expect_err("query timed out");
+    assert!(error.is_timeout());
+    assert!(error.is_interrupted());
+    assert_eq!(error.duckdb_error_kind(), Some(DuckDbErrorKind::Timeout));
+    // The message is DuckDB's own
+    assert_eq!(
+        DuckDbErrorKind::from_message(&error.to_string()),
+        DuckDbErrorKind::Interrupt
+    );
+
+    let error = conn.batch_execute(SLOW_QUERY).expect_err("batch timed out");
+    assert!(error.is_timeout());
+
+    // fast queries are unaffected
+    for _ in 0..3 {
+        let one = diesel::select(sql::<BigInt>("1")).get_result::<i64>(&mut conn);
+        assert_eq!(one, Ok(1));
+    }
+}
+
+#[test]
+fn test_with_timeout() {
+    let mut conn = setup_basic_connection();
+
+    let result = conn.with_timeout(Duration::from_millis(100), slow_query);
+    assert!(result.expect_err("query timed out").is_timeout());
+    assert_eq!(conn.query_timeout(), None);
+}
+
+#[test]
+fn test_query_timeout_option() {
+    let conn = DuckDbConnectionBuilder::from_url(":memory:?query_timeout_ms=250")
+        .unwrap()
+        .establish()
+        .unwrap();
+    assert_eq!(conn.query_timeout(), Some(Duration::from_millis(250)));
+
+    let clone = conn.try_clone().unwrap();
+    assert_eq!(clone.query_timeout(), Some(Duration::from_millis(250)));
+
+    assert!(DuckDbConnectionBuilder::from_url(":memory:?query_timeout_ms=soon").is_err());
+}
+
+#[test]
+fn test_errors_are_not_interrupts() {
+    let mut conn = setup_basic_connection();
+    let error = conn.batch_execute("SELECT * FROM missing").unwrap_err();
+    assert!(!error.is_interrupted());
+    assert!(!error.is_timeout());
+}
@@ -0,0 +1,84 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+
+use super::{setup_basic_connection, setup_users_table, temp_database_path};
+use crate::{DuckDbConnection, DuckDbErrorExt, DuckDbErrorKind};
+
+fn main_database_size(conn: &mut DuckDbConnection) -> crate::DatabaseSize {
+    let sizes = conn.database_size().unwrap();
+    sizes
+        .into_iter()
+        .find(|size| size.database_name == "test")
+        .unwrap()
+}
+
+#[test]
+fn test_checkpoint_empties_wal() {
+    let path = temp_database_path("checkpoint");
+    let mut conn = DuckDbConnection::establish(path.to_str().unwrap()).unwrap();
+    conn.batch_execute("CREATE TABLE numbers AS SELECT range AS n FROM range(100000)")
+        .unwrap();
+
+    let before = main_database_size(&mut conn);
+    assert_ne!(before.wal_size.as_deref(), Some("0 bytes"));
+
+    conn.checkpoint().unwrap();
+    let after = main_database_size(&mut conn);
+    assert_eq!(after.wal_size.as_deref(), Some("0 bytes"));
+    assert!(after.used_blocks > 0);
+    assert_eq!(after.total_blocks, after.used_blocks + after.free_blocks);
+    assert_eq!(after.bytes(), after.block_size * after.total_blocks);
+
+    conn.force_checkpoint().unwrap();
+}
+
+#[test]
+fn test_database_size_lists_attached_databases() {
+    let mut conn = setup_basic_connection();
+    conn.batch_execute("ATTACH ':memory:' AS scratch").unwrap();
+
+    let mut names = conn
+        .database_size()
+        .unwrap()
+        .into_iter()
+        .map(|size| size.database_name)
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["memory", "scratch"]);
+}
+
+#[test]
+fn test_vacuum_and_analyze() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    conn.batch_execute("CREATE TABLE \"old.users\" (id INTEGER)")
+        .unwrap();
+
+    conn.vacuum(None, "users").unwrap();
+    conn.analyze(None, "users").unwrap();
+    conn.vacuum(None, "old.users").unwrap();
+    conn.analyze(None, "old.users").unwrap();
+
+    let error = conn.analyze(None, "missing").unwrap_err();
+    assert_eq!(error.duckdb_error_kind(), Some(DuckDbErrorKind::Catalog));
+    // Names are quoted, not interpolated
+    assert!(conn.vacuum(None, "users; DROP TABLE users").is_err());
+    conn.batch_execute("SELECT * FROM users").unwrap();
+}
+
+#[test]
+fn test_vacuum_and_analyze_qualified_tables() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    conn.batch_execute("ATTACH ':memory:' AS scratch; CREATE TABLE scratch.events (id INTEGER)")
+        .unwrap();
+
+    conn.vacuum(Some("main"), "users").unwrap();
+    conn.analyze(Some("main"), "users").unwrap();
+    conn.analyze(Some("scratch"), "events").unwrap();
+
+    let error = conn.analyze(Some("scratch"), "users").unwrap_err();
+    assert_eq!(error.duckdb_error_kind(), Some(DuckDbErrorKind::Catalog));
+    // The schema is quoted as one name too
+    assert!(conn.analyze(Some("main.main"), "users").is_err());
+}
@@ -1,5 +1,34 @@
 mod schema;
 mod chrono_test;
+mod connection_builder_test;
+mod conversion_test;
+mod read_only_test;
+mod attach_test;
+#[cfg(feature = "r2d2")]
+mod r2d2_test;
+mod try_clone_test;
+#[cfg(feature = "async")]
+mod async_test;
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+mod pool_test;
+mod instrumentation_test;
+#[cfg(feature = "tracing")]
+mod tracing_test;
+mod explain_test;
+mod interrupt_test;
+mod error_test;
+mod retry_test;
+mod transaction_test;
+mod maintenance_test;
+mod export_test;
+mod copy_test;
+mod table_function_test;
+#[cfg(feature = "vtab")]
+mod virtual_table_test;
+#[cfg(feature = "vscalar")]
+mod scalar_function_test;
+#[cfg(feature = "vscalar")]
+mod aggregate_function_test;
 
 use crate::DuckDbConnection;
 use chrono::{NaiveDate, NaiveDateTime};
@@ -34,6 +63,14 @@ fn setup_basic_connection() -> DuckDbConnection {
     DuckDbConnection::establish(":memory:").unwrap()
 }
 
+// Fresh directory for tests that need database files, returns the database path in it
+fn temp_database_path(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("diesel_duckdb_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("test.duckdb")
+}
+
 fn setup_users_table(conn: &mut DuckDbConnection) {
     conn.batch_execute(
         "
@@ -0,0 +1,152 @@
+use diesel::connection::{SimpleConnection, TransactionManager};
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+
+use super::schema::users;
+use super::setup_users_table;
+use crate::{
+    AsyncDuckDbConnection, DuckDbConnection, DuckDbConnectionBuilder, DuckDbTransactionManager,
+};
+
+fn shared_builder() -> DuckDbConnectionBuilder {
+    DuckDbConnection::builder(":memory:")
+}
+
+// Temp tables are per connection, so this tells whether the init SQL ran on it
+const INIT_SQL: &str = "CREATE TEMP TABLE initialized AS SELECT 42 AS answer";
+
+async fn init_sql_ran(conn: &AsyncDuckDbConnection) -> bool {
+    conn.get_result::<_, i64>(diesel::select(sql::<BigInt>(
+        "(SELECT CAST(answer AS BIGINT) FROM initialized)",
+    )))
+    .await
+    .is_ok()
+}
+
+async fn setup_users(conn: &AsyncDuckDbConnection) {
+    conn.interact(|conn| {
+        setup_users_table(conn);
+        Ok(())
+    })
+    .await
+    .unwrap();
+}
+
+// Leave a transaction open with an uncommitted user in it
+async fn leave_transaction_open(conn: &AsyncDuckDbConnection) {
+    conn.interact(|conn| {
+        DuckDbTransactionManager::begin_transaction(conn)?;
+        conn.batch_execute("INSERT INTO users (id, name) VALUES (99, 'Dangling')")
+    })
+    .await
+    .unwrap();
+}
+
+async fn user_count(conn: &AsyncDuckDbConnection) -> i64 {
+    conn.get_result(users::table.count()).await.unwrap()
+}
+
+#[cfg(feature = "deadpool")]
+mod deadpool {
+    use super::*;
+    use crate::deadpool::{DuckDbConnectionManager, Pool};
+
+    #[tokio::test]
+    async fn test_deadpool_runs_init_sql_and_shares_database() {
+        let manager = DuckDbConnectionManager::shared(shared_builder())
+            .unwrap()
+            .with_init_sql(INIT_SQL);
+        let pool = Pool::builder(manager).max_size(2).build().unwrap();
+
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        assert!(init_sql_ran(&first).await);
+        assert!(init_sql_ran(&second).await);
+
+        setup_users(&first).await;
+        assert_eq!(user_count(&second).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_deadpool_rolls_back_dangling_transactions() {
+        let manager = DuckDbConnectionManager::shared(shared_builder()).unwrap();
+        let pool = Pool::builder(manager).max_size(1).build().unwrap();
+
+        let conn = pool.get().await.unwrap();
+        setup_users(&conn).await;
+        leave_transaction_open(&conn).await;
+        drop(conn);
+
+        let conn = pool.get().await.unwrap();
+        let broken = conn
+            .interact(|conn| {
+                Ok(DuckDbTransactionManager::is_broken_transaction_manager(
+                    conn,
+                ))
+            })
+            .await
+            .unwrap();
+        assert!(!broken);
+        assert_eq!(user_count(&conn).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_deadpool_reports_connection_errors() {
+        let manager =
+            DuckDbConnectionManager::new(DuckDbConnection::builder("/nonexistent/dir/test.duckdb"));
+        let pool = Pool::builder(manager).max_size(1).build().unwrap();
+        assert!(pool.get().await.is_err());
+
+        let manager =
+            DuckDbConnectionManager::new(shared_builder()).with_init_sql("SELECT * FROM missing");
+        let pool = Pool::builder(manager).max_size(1).build().unwrap();
+        assert!(pool.get().await.is_err());
+    }
+}
+
+#[cfg(feature = "bb8")]
+mod bb8 {
+    use super::*;
+    use crate::bb8::{DuckDbConnectionManager, Pool};
+
+    #[tokio::test]
+    async fn test_bb8_runs_init_sql_and_shares_database() {
+        let manager = DuckDbConnectionManager::shared(shared_builder())
+            .unwrap()
+            .with_init_sql(INIT_SQL);
+        let pool = Pool::builder().max_size(2).build(manager).await.unwrap();
+
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        assert!(init_sql_ran(&first).await);
+        assert!(init_sql_ran(&second).await);
+
+        setup_users(&first).await;
+        assert_eq!(user_count(&second).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_bb8_rolls_back_dangling_transactions() {
+        let manager = DuckDbConnectionManager::shared(shared_builder()).unwrap();
+        let pool = Pool::builder().max_size(1).build(manager).await.unwrap();
+
+        let conn = pool.get().await.unwrap();
+        setup_users(&conn).await;
+        leave_transaction_open(&conn).await;
+        drop(conn);
+
+        let conn = pool.get().await.unwrap();
+        let broken = conn
+            .interact(|conn| {
+                Ok(DuckDbTransactionManager::is_broken_transaction_manager(
+                    conn,
+                ))
+            })
+            .await
+            .unwrap();
+        assert!(!broken);
+        assert_eq!(user_count(&conn).await, 0);
+        assert_eq!(pool.state().statistics.connections_closed_broken, 1);
+    }
+}
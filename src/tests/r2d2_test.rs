@@ -0,0 +1,83 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, R2D2Connection};
+
+use super::schema::users;
+use super::setup_users_table;
+use crate::r2d2::DuckDbConnectionManager;
+use crate::{DuckDbConnection, DuckDbConnectionBuilder};
+
+#[test]
+fn test_diesel_connection_manager() {
+    let pool = Pool::builder()
+        .max_size(2)
+        .build(ConnectionManager::<DuckDbConnection>::new(":memory:"))
+        .unwrap();
+
+    let mut conn = pool.get().unwrap();
+    conn.ping().unwrap();
+    setup_users_table(&mut conn);
+    let count: i64 = users::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_shared_in_memory_database_across_pool() {
+    let manager = DuckDbConnectionManager::from_url(":memory:?threads=2").unwrap();
+    let pool = Pool::builder().max_size(4).build(manager).unwrap();
+
+    let mut first = pool.get().unwrap();
+    setup_users_table(&mut first);
+    first
+        .batch_execute("INSERT INTO users (id, name) VALUES (1, 'Alice')")
+        .unwrap();
+
+    let handles = (0..4)
+        .map(|_| {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                let mut conn = pool.get().unwrap();
+                users::table
+                    .select(users::name)
+                    .first::<Option<String>>(&mut conn)
+                    .unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(first);
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), Some("Alice".to_string()));
+    }
+}
+
+#[test]
+fn test_shared_pool_keeps_read_only_mode() {
+    let path = super::temp_database_path("r2d2_read_only");
+    let path = path.to_str().unwrap();
+    {
+        let mut writer = DuckDbConnection::establish(path).unwrap();
+        setup_users_table(&mut writer);
+    }
+
+    let manager =
+        DuckDbConnectionManager::new(DuckDbConnectionBuilder::new(path).read_only()).unwrap();
+    let pool = Pool::builder().max_size(2).build(manager).unwrap();
+    let mut conn = pool.get().unwrap();
+
+    assert!(conn.is_read_only());
+    assert!(diesel::delete(users::table).execute(&mut conn).is_err());
+}
+
+#[test]
+fn test_connection_broken_inside_transaction() {
+    let mut conn = DuckDbConnection::establish(":memory:").unwrap();
+    assert!(!conn.is_broken());
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        assert!(conn.is_broken());
+        Ok(())
+    })
+    .unwrap();
+    assert!(!conn.is_broken());
+}
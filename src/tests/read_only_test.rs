@@ -0,0 +1,195 @@
+use diesel::connection::SimpleConnection;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+use super::schema::users;
+use super::{setup_users_table, temp_database_path};
+use crate::DuckDbConnection;
+
+fn create_database(name: &str) -> String {
+    let path = temp_database_path(name);
+    let path = path.to_str().unwrap().to_string();
+
+    let mut writer = DuckDbConnection::establish(&path).unwrap();
+    setup_users_table(&mut writer);
+    writer
+        .batch_execute(
+            "INSERT INTO users (id, name, email) VALUES (1, 'Alice', 'alice@example.com')",
+        )
+        .unwrap();
+    path
+}
+
+fn is_read_only_error<T: std::fmt::Debug>(result: QueryResult<T>) -> bool {
+    matches!(
+        result,
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::ReadOnlyTransaction,
+            _
+        ))
+    )
+}
+
+#[test]
+fn test_read_only_rejects_writes() {
+    let path = create_database("read_only_rejects");
+    let mut reader = DuckDbConnection::builder(path.as_str())
+        .read_only()
+        .establish()
+        .unwrap();
+    assert!(reader.is_read_only());
+
+    let count: i64 = users::table
+        .select(count_star())
+        .first(&mut reader)
+        .unwrap();
+    assert_eq!(count, 1);
+
+    assert!(is_read_only_error(
+        diesel::insert_into(users::table)
+            .values((users::id.eq(2), users::name.eq("Bob")))
+            .execute(&mut reader)
+    ));
+    assert!(is_read_only_error(
+        diesel::delete(users::table).execute(&mut reader)
+    ));
+    assert!(is_read_only_error(
+        reader.batch_execute("-- harmless\n  create table t (id INTEGER)")
+    ));
+    assert!(is_read_only_error(reader.batch_execute(
+        "WITH new_users AS (SELECT 2 AS id) INSERT INTO users (id) SELECT id FROM new_users"
+    )));
+    assert!(is_read_only_error(
+        reader.batch_execute("EXPLAIN ANALYZE DELETE FROM users")
+    ));
+    let csv = temp_database_path("read_only_rejects_copy").with_extension("csv");
+    std::fs::write(&csv, "id,name,email\n3,Carol,carol@example.com\n").unwrap();
+    assert!(is_read_only_error(
+        diesel::sql_query(format!("COPY users (id, name, email) FROM '{}'", csv.display()))
+            .execute(&mut reader)
+    ));
+
+    // Temporary tables live outside the database and can still be written
+    reader
+        .batch_execute("CREATE TEMP TABLE scratch AS SELECT 'DROP TABLE users' AS note")
+        .unwrap();
+    let message = match reader.batch_execute("DROP TABLE users") {
+        Err(DieselError::DatabaseError(_, info)) => info.message().to_string(),
+        other => panic!("Expected a database error, got {:?}", other),
+    };
+    assert!(message.contains("DROP"));
+    assert!(message.contains("read-only"));
+}
+
+#[test]
+fn test_read_only_from_url() {
+    let path = create_database("read_only_url");
+    let mut reader =
+        DuckDbConnection::establish(&format!("{}?access_mode=read_only", path)).unwrap();
+
+    assert!(reader.is_read_only());
+    assert!(is_read_only_error(
+        diesel::update(users::table)
+            .set(users::name.eq("Eve"))
+            .execute(&mut reader)
+    ));
+}
+
+#[test]
+fn test_reopen_sees_new_writes() {
+    let path = create_database("read_only_reopen");
+    let mut reader = DuckDbConnection::builder(path.as_str())
+        .read_only()
+        .establish()
+        .unwrap();
+    let names = || users::table.select(users::name).order(users::id);
+    assert_eq!(
+        names().load::<Option<String>>(&mut reader).unwrap(),
+        vec![Some("Alice".to_string())]
+    );
+
+    {
+        let mut writer = DuckDbConnection::establish(&path).unwrap();
+        writer
+            .batch_execute("INSERT INTO users (id, name) VALUES (2, 'Bob'); CHECKPOINT")
+            .unwrap();
+    }
+
+    reader.reopen().unwrap();
+    assert!(reader.is_read_only());
+    assert_eq!(
+        names().load::<Option<String>>(&mut reader).unwrap(),
+        vec![Some("Alice".to_string()), Some("Bob".to_string())]
+    );
+}
+
+#[test]
+fn test_reopen_requires_builder_and_no_transaction() {
+    let mut conn =
+        DuckDbConnection::establish_with_flags(":memory:", duckdb::Config::default()).unwrap();
+    assert!(!conn.is_read_only());
+    assert!(conn.reopen().is_err());
+
+    // Reopening would lose the data of an in-memory database
+    let mut conn = DuckDbConnection::establish(":memory:").unwrap();
+    assert!(conn.reopen().is_err());
+
+    let path = create_database("reopen_requirements");
+    let mut conn = DuckDbConnection::establish(&path).unwrap();
+    conn.transaction::<_, DieselError, _>(|conn| {
+        assert!(conn.reopen().is_err());
+        Ok(())
+    })
+    .unwrap();
+
+    // A clone shares the database of the connection it was made from
+    let mut clone = conn.try_clone().unwrap();
+    assert!(clone.reopen().is_err());
+    drop(clone);
+
+    conn.reopen().unwrap();
+    assert_eq!(users::table.count().get_result(&mut conn), Ok(1));
+}
+
+// Put a directory in place of the database file so that opening it fails
+fn make_unopenable(path: &str) {
+    std::fs::remove_file(path).unwrap();
+    std::fs::create_dir(path).unwrap();
+}
+
+#[test]
+fn test_failed_reopen_keeps_read_only_database() {
+    let path = create_database("reopen_read_only_failure");
+    let mut reader = DuckDbConnection::builder(path.as_str())
+        .read_only()
+        .establish()
+        .unwrap();
+
+    make_unopenable(&path);
+    assert!(reader.reopen().is_err());
+    assert_eq!(users::table.count().get_result(&mut reader), Ok(1));
+}
+
+#[test]
+fn test_failed_reopen_closes_writable_database() {
+    let path = create_database("reopen_writable_failure");
+    let mut conn = DuckDbConnection::establish(&path).unwrap();
+
+    make_unopenable(&path);
+    assert!(conn.reopen().is_err());
+    assert!(matches!(
+        users::table.count().get_result::<i64>(&mut conn),
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::ClosedConnection,
+            _
+        ))
+    ));
+    assert!(conn.batch_execute("SELECT 1").is_err());
+    assert!(conn.try_clone().is_err());
+
+    // Reopening again once the database can be opened recovers
+    std::fs::remove_dir(&path).unwrap();
+    conn.reopen().unwrap();
+    conn.batch_execute("SELECT 1").unwrap();
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::Integer;
+
+use super::setup_basic_connection;
+use crate::{DuckDbConnection, RetryPolicy};
+
+fn setup_counters() -> (DuckDbConnection, DuckDbConnection) {
+    let mut conn = setup_basic_connection();
+    conn.batch_execute(
+        "CREATE TABLE counters (id INTEGER PRIMARY KEY, value INTEGER);
+         INSERT INTO counters VALUES (1, 0);",
+    )
+    .unwrap();
+    let other = conn.try_clone().unwrap();
+    (conn, other)
+}
+
+fn counter(conn: &mut DuckDbConnection) -> i32 {
+    diesel::select(sql::<Integer>("(SELECT value FROM counters WHERE id = 1)"))
+        .get_result(conn)
+        .unwrap()
+}
+
+fn policy() -> RetryPolicy {
+    RetryPolicy::new().backoff(Duration::from_millis(1))
+}
+
+#[test]
+fn test_conflicts_are_serialization_failures() {
+    let (mut conn, mut other) = setup_counters();
+
+    // DuckDB starts the transaction with its first statement
+    conn.batch_execute("BEGIN; SELECT * FROM counters").unwrap();
+    other
+        .batch_execute("UPDATE counters SET value = 10 WHERE id = 1")
+        .unwrap();
+    let result = conn.batch_execute("UPDATE counters SET value = value + 1 WHERE id = 1");
+    assert!(matches!(
+        result,
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::SerializationFailure,
+            _
+        ))
+    ));
+}
+
+#[test]
+fn test_transaction_with_retry() {
+    let (mut conn, mut other) = setup_counters();
+    let mut attempts = 0;
+
+    let value = conn
+        .transaction_with_retry(&policy(), |conn| {
+            attempts += 1;
+            let before = counter(conn);
+            if attempts == 1 {
+                // a concurrent writer changes the row after the transaction started
+                other.batch_execute("UPDATE counters SET value = 10 WHERE id = 1")?;
+            }
+            conn.batch_execute("UPDATE counters SET value = value + 1 WHERE id = 1")?;
+            Ok((before, counter(conn)))
+        })
+        .unwrap();
+
+    assert_eq!(attempts, 2);
+    assert_eq!(value, (10, 11));
+    assert_eq!(counter(&mut other), 11);
+}
+
+#[test]
+fn test_retry_gives_up() {
+    let (mut conn, mut other) = setup_counters();
+    let mut attempts = 0;
+
+    let result = conn.transaction_with_retry(&policy().max_attempts(3), |conn| {
+        attempts += 1;
+        counter(conn);
+        other.batch_execute("UPDATE counters SET value = value + 10 WHERE id = 1")?;
+        conn.batch_execute("UPDATE counters SET value = value + 1 WHERE id = 1")
+    });
+
+    assert!(matches!(
+        result,
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::SerializationFailure,
+            _
+        ))
+    ));
+    assert_eq!(attempts, 3);
+    assert_eq!(counter(&mut conn), 30);
+}
+
+#[test]
+fn test_other_errors_are_not_retried() {
+    let (mut conn, _) = setup_counters();
+    let mut attempts = 0;
+
+    let result = conn.transaction_with_retry(&policy(), |conn| {
+        attempts += 1;
+        conn.batch_execute("INSERT INTO counters VALUES (1, 0)")
+    });
+
+    assert!(matches!(
+        result,
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            _
+        ))
+    ));
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn test_failed_commit_leaves_connection_usable() {
+    let (mut conn, mut other) = setup_counters();
+
+    let result = conn.transaction_with_retry(&policy(), |conn| {
+        other.batch_execute("BEGIN; INSERT INTO counters VALUES (5, 0)")?;
+        conn.batch_execute("INSERT INTO counters VALUES (5, 1)")?;
+        other.batch_execute("COMMIT")
+    });
+    // the key was taken by the other transaction when committing
+    assert!(matches!(
+        result,
+        Err(DieselError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            _
+        ))
+    ));
+
+    let value = conn.transaction(|conn| QueryResult::Ok(counter(conn)));
+    assert_eq!(value, Ok(0));
+}
+
+#[test]
+fn test_retry_inside_transaction() {
+    let (mut conn, _) = setup_counters();
+
+    let result = conn.transaction(|conn| conn.transaction_with_retry(&policy(), |_| Ok(())));
+    assert!(matches!(result, Err(DieselError::AlreadyInTransaction)));
+}
@@ -0,0 +1,215 @@
+use chrono::NaiveDate;
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Integer, Nullable, Text};
+
+use super::schema::users;
+use super::{setup_basic_connection, setup_users_with_basic_data};
+
+diesel::define_sql_function!(fn initials(name: Nullable<Text>) -> Nullable<Text>);
+diesel::define_sql_function!(fn or_missing(value: Nullable<Text>) -> Text);
+diesel::define_sql_function!(fn times(a: Integer, b: Integer) -> BigInt);
+diesel::define_sql_function!(fn next_day(day: Date) -> Date);
+
+fn register_times(conn: &mut crate::DuckDbConnection) {
+    conn.register_scalar_function::<(Integer, Integer), BigInt, _>("times", |(a, b)| {
+        Ok(i64::from(a) * i64::from(b))
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_call_from_dsl() {
+    let mut conn = setup_users_with_basic_data();
+    conn.register_scalar_function::<(Nullable<Text>,), Nullable<Text>, _>("initials", |(name,)| {
+        Ok(name.map(|name| {
+            name.split_whitespace()
+                .filter_map(|word| word.chars().next())
+                .collect()
+        }))
+    })
+    .unwrap();
+    register_times(&mut conn);
+
+    let rows = users::table
+        .select((initials(users::name), times(users::id, 10)))
+        .filter(times(users::id, users::id).gt(1))
+        .order(users::id)
+        .load::<(Option<String>, i64)>(&mut conn)
+        .unwrap();
+    assert_eq!(
+        rows,
+        [(Some("JS".to_string()), 20), (Some("BJ".to_string()), 30)]
+    );
+}
+
+#[test]
+fn test_null_arguments() {
+    let mut conn = setup_users_with_basic_data();
+    conn.batch_execute("INSERT INTO users (id, name) VALUES (4, NULL)")
+        .unwrap();
+    conn.register_scalar_function::<(Nullable<Text>,), Text, _>("or_missing", |(value,)| {
+        Ok(value.unwrap_or_else(|| "missing".to_string()))
+    })
+    .unwrap();
+    register_times(&mut conn);
+
+    let names = users::table
+        .select(or_missing(users::name))
+        .filter(users::id.ge(3))
+        .order(users::id)
+        .load::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(names, ["Bob Johnson", "missing"]);
+    let name = diesel::select(or_missing(None::<String>))
+        .get_result::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(name, "missing");
+
+    // not called for NULL arguments that are not nullable
+    let products = users::table
+        .select(diesel::dsl::sql::<Nullable<BigInt>>("times(age, 2)"))
+        .order(users::id.desc())
+        .load::<Option<i64>>(&mut conn)
+        .unwrap();
+    assert_eq!(products, [None, Some(70), Some(50), Some(60)]);
+}
+
+#[test]
+fn test_dates() {
+    let mut conn = setup_basic_connection();
+    conn.register_scalar_function::<(Date,), Date, _>("next_day", |(day,)| {
+        day.succ_opt().ok_or_else(|| "no next day".into())
+    })
+    .unwrap();
+
+    let day = diesel::dsl::sql::<Date>("DATE '2025-07-31'");
+    let next = diesel::select(next_day(day))
+        .get_result::<NaiveDate>(&mut conn)
+        .unwrap();
+    assert_eq!(next, NaiveDate::from_ymd_opt(2025, 8, 1).unwrap());
+}
+
+#[test]
+fn test_more_rows_than_a_chunk() {
+    let mut conn = setup_basic_connection();
+    register_times(&mut conn);
+
+    let sum = diesel::select(diesel::dsl::sql::<BigInt>(
+        "(SELECT sum(times(i::INTEGER, 2)) FROM range(5000) t(i))",
+    ))
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+    assert_eq!(sum, (0..5000).sum::<i64>() * 2);
+}
+
+#[test]
+fn test_error_fails_query() {
+    let mut conn = setup_users_with_basic_data();
+    conn.register_scalar_function::<(Integer, Integer), BigInt, _>("times", |(a, b)| {
+        i64::from(a)
+            .checked_mul(i64::from(b))
+            .filter(|_| a != 3)
+            .ok_or_else(|| format!("cannot multiply {}", a).into())
+    })
+    .unwrap();
+
+    let error = users::table
+        .select(times(users::id, 2))
+        .load::<i64>(&mut conn)
+        .unwrap_err();
+    assert!(error.to_string().contains("cannot multiply 3"));
+}
+
+#[test]
+fn test_panic_fails_query() {
+    let mut conn = setup_basic_connection();
+    conn.register_scalar_function::<(Integer, Integer), BigInt, _>("times", |_| {
+        panic!("boom");
+    })
+    .unwrap();
+
+    let error = conn.batch_execute("SELECT times(1, 2)").unwrap_err();
+    assert!(error.to_string().contains("scalar function panicked: boom"));
+}
+
+#[test]
+fn test_register_again_and_unregister() {
+    let mut conn = setup_basic_connection();
+    register_times(&mut conn);
+    conn.register_scalar_function::<(Integer, Integer), BigInt, _>("times", |(a, b)| {
+        Ok(i64::from(a) + i64::from(b))
+    })
+    .unwrap();
+
+    let result = diesel::select(times(2, 5))
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(result, 7);
+
+    conn.unregister_scalar_function("times").unwrap();
+    assert!(diesel::select(times(2, 5))
+        .get_result::<i64>(&mut conn)
+        .is_err());
+    // unknown names are ignored
+    conn.unregister_scalar_function("times").unwrap();
+}
+
+#[test]
+fn test_closures_of_another_connection_are_rejected() {
+    let mut conn = setup_basic_connection();
+    register_times(&mut conn);
+    let key = diesel::dsl::sql::<Text>(
+        "SELECT regexp_extract(macro_definition, '(\\d+),', 1) \
+         FROM duckdb_functions() WHERE function_name = 'times'",
+    )
+    .get_result::<String>(&mut conn)
+    .unwrap();
+
+    let mut other = conn.try_clone().unwrap();
+    register_times(&mut other);
+    let error = other
+        .batch_execute(&format!(
+            "SELECT __diesel_duckdb_function_integer_integer_to_bigint(\
+             getvariable('__diesel_duckdb_connection'), {}, 2, 5)",
+            key
+        ))
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("data of another connection"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn test_one_function_per_signature() {
+    let mut conn = setup_basic_connection();
+    let mut other = conn.try_clone().unwrap();
+    register_times(&mut conn);
+    register_times(&mut other);
+    conn.register_scalar_function::<(Integer, Integer), BigInt, _>("plus", |(a, b)| {
+        Ok(i64::from(a) + i64::from(b))
+    })
+    .unwrap();
+    conn.register_scalar_function::<(Nullable<Text>,), Text, _>("or_missing", |(value,)| {
+        Ok(value.unwrap_or_else(|| "missing".to_string()))
+    })
+    .unwrap();
+
+    let functions = diesel::dsl::sql::<Text>(
+        "SELECT string_agg(DISTINCT function_name, ', ' ORDER BY function_name) \
+         FROM duckdb_functions() WHERE starts_with(function_name, '__diesel_duckdb')",
+    )
+    .get_result::<String>(&mut conn)
+    .unwrap();
+    assert_eq!(
+        functions,
+        "__diesel_duckdb_function_integer_integer_to_bigint, \
+         __diesel_duckdb_function_nullable_text_to_text"
+    );
+    let result = diesel::select(times(2, 5))
+        .get_result::<i64>(&mut other)
+        .unwrap();
+    assert_eq!(result, 10);
+}
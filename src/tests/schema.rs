@@ -22,3 +22,40 @@ diesel::table! {
 diesel::joinable!(orders -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(orders, users,);
+
+diesel::table! {
+    analytics.events (event_id) {
+        event_id -> Integer,
+        user_id -> Integer,
+        kind -> VarChar,
+    }
+}
+
+crate::catalog_table! {
+    #[sql_name = "events"]
+    analytics.archive.archived_events (event_id) {
+        event_id -> Integer,
+        user_id -> Integer,
+        kind -> VarChar,
+    }
+}
+
+diesel::joinable!(events -> users (user_id));
+
+diesel::allow_tables_to_appear_in_same_query!(archived_events, events, users,);
+
+crate::table_function! {
+    logins (user_id, device) {
+        user_id -> Integer,
+        device -> Text,
+    }
+}
+
+crate::table_function! {
+    devices (device) {
+        device -> Text,
+        platform -> Text,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(devices, logins, users,);
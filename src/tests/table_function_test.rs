@@ -0,0 +1,175 @@
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+
+use super::schema::{devices, logins, users};
+use super::{setup_users_with_basic_data, temp_database_path};
+use crate::{TableFunction, TableFunctionSource};
+
+// Directory of login CSV files, returns the glob matching all of them
+fn write_logins(test: &str) -> String {
+    let dir = temp_database_path(test).parent().unwrap().join("logins");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("monday.csv"),
+        "user_id|device|seconds\n1|laptop|30\n2|phone|12\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("tuesday.csv"),
+        "user_id|device|seconds\n1|phone|5\n3|laptop|44\n",
+    )
+    .unwrap();
+    dir.join("*.csv").to_str().unwrap().to_string()
+}
+
+fn read_logins(glob: &str) -> TableFunction {
+    TableFunction::read_csv(glob).option("delim", "|")
+}
+
+#[test]
+fn test_select_and_filter() {
+    let glob = write_logins("table_function_select");
+    let mut conn = setup_users_with_basic_data();
+
+    let phone_users = logins::table
+        .read_from(read_logins(&glob))
+        .filter(logins::device.eq("phone"))
+        .select(logins::user_id)
+        .order(logins::user_id)
+        .load::<i32>(&mut conn)
+        .unwrap();
+    assert_eq!(phone_users, [1, 2]);
+
+    let count = logins::table
+        .read_from(read_logins(&glob))
+        .select(count_star())
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(count, 4);
+}
+
+#[test]
+fn test_join_with_table() {
+    let glob = write_logins("table_function_join");
+    let mut conn = setup_users_with_basic_data();
+
+    let rows = logins::table
+        .read_from(read_logins(&glob))
+        .inner_join(users::table.on(users::id.eq(logins::user_id)))
+        .filter(users::age.ge(30))
+        .select((users::name, logins::device))
+        .order((users::id, logins::device))
+        .load::<(Option<String>, String)>(&mut conn)
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            (Some("John Doe".to_string()), "laptop".to_string()),
+            (Some("John Doe".to_string()), "phone".to_string()),
+            (Some("Bob Johnson".to_string()), "laptop".to_string()),
+        ]
+    );
+
+    let logins = logins::table.read_from(read_logins(&glob));
+    let count = users::table
+        .left_join(logins.on(logins::user_id.eq(users::id)))
+        .filter(logins::device.is_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_several_table_functions() {
+    let glob = write_logins("table_function_several");
+    let dir = std::path::Path::new(&glob).parent().unwrap();
+    let monday = dir.join("monday.csv").to_str().unwrap().to_string();
+    let tuesday = dir.join("tuesday.csv").to_str().unwrap().to_string();
+    let devices_csv = dir.join("devices.txt");
+    std::fs::write(
+        &devices_csv,
+        "device,platform\nlaptop,linux\nphone,android\n",
+    )
+    .unwrap();
+    let mut conn = setup_users_with_basic_data();
+
+    let devices = devices::table
+        .read_from(TableFunction::read_csv(devices_csv.to_str().unwrap()).option("header", true));
+    let rows = logins::table
+        .read_from(read_logins(&monday).path(tuesday))
+        .inner_join(devices.on(devices::device.eq(logins::device)))
+        .filter(devices::platform.eq("android"))
+        .select(logins::user_id)
+        .order(logins::user_id)
+        .load::<i32>(&mut conn)
+        .unwrap();
+    assert_eq!(rows, [1, 2]);
+}
+
+#[test]
+fn test_paths_are_bound() {
+    let mut conn = setup_users_with_basic_data();
+
+    let result = logins::table
+        .read_from(TableFunction::read_csv("missing'); DROP TABLE users; --"))
+        .select(logins::user_id)
+        .load::<i32>(&mut conn);
+    assert!(result.is_err());
+    assert_eq!(users::table.count().get_result::<i64>(&mut conn), Ok(3));
+}
+
+#[test]
+fn test_function_shadows_table_of_the_same_name() {
+    use diesel::connection::SimpleConnection;
+
+    let glob = write_logins("table_function_shadows");
+    let mut conn = setup_users_with_basic_data();
+    conn.batch_execute("CREATE TABLE logins (user_id INTEGER, device VARCHAR)")
+        .unwrap();
+
+    let sql = diesel::debug_query::<crate::DuckDb, _>(
+        &logins::table
+            .read_from(read_logins(&glob))
+            .select(logins::user_id),
+    )
+    .to_string();
+    assert!(sql.starts_with(
+        "SELECT \"logins\".\"user_id\" FROM read_csv(?, \"delim\" = ?) AS \"logins\""
+    ));
+
+    let count = logins::table
+        .read_from(read_logins(&glob))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(count, 4);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_read_parquet() {
+    use crate::{CopyFormat, CopyOptions};
+
+    let glob = write_logins("table_function_parquet");
+    let path = std::path::Path::new(&glob)
+        .parent()
+        .unwrap()
+        .join("logins.parquet");
+    let path = path.to_str().unwrap();
+    let mut conn = setup_users_with_basic_data();
+    let all_logins = logins::table
+        .read_from(read_logins(&glob))
+        .select((logins::user_id, logins::device));
+    conn.copy_to(all_logins, path, CopyFormat::Parquet, &CopyOptions::new())
+        .unwrap();
+
+    let devices = logins::table
+        .read_from(TableFunction::read_parquet(path))
+        .filter(logins::user_id.eq(1))
+        .select(logins::device)
+        .order(logins::device)
+        .load::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(devices, ["laptop", "phone"]);
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use super::schema::users;
+use super::{setup_basic_connection, setup_users_table};
+use crate::MapDieselError;
+
+type Fields = BTreeMap<String, String>;
+// An event with the index of the span it happened in
+type SpanEvent = (Option<usize>, Fields);
+
+// Minimal subscriber keeping the fields of every span and event
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<Fields>>>,
+    events: Arc<Mutex<Vec<SpanEvent>>>,
+    entered: Arc<Mutex<Vec<usize>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::new();
+        fields.insert("name".to_string(), span.metadata().name().to_string());
+        span.record(&mut FieldVisitor(&mut fields));
+
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(fields);
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut FieldVisitor(&mut spans[span.into_u64() as usize - 1]));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        fields.insert("level".to_string(), event.metadata().level().to_string());
+        event.record(&mut FieldVisitor(&mut fields));
+        let span = self.entered.lock().unwrap().last().copied();
+        self.events.lock().unwrap().push((span, fields));
+    }
+
+    fn enter(&self, span: &Id) {
+        let index = span.into_u64() as usize - 1;
+        self.entered.lock().unwrap().push(index);
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn test_query_spans() {
+    let recorder = Recorder::default();
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("Alice")))
+            .execute(&mut conn)
+            .unwrap();
+        for _ in 0..2 {
+            users::table
+                .select(users::name)
+                .filter(users::id.gt(0))
+                .load::<Option<String>>(&mut conn)
+                .unwrap();
+        }
+        conn.batch_execute("SELECT 1; SELECT 2").unwrap();
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    assert_eq!(spans.len(), 4);
+    assert!(spans.iter().all(|span| span["name"] == "duckdb.query"));
+    assert!(spans.iter().all(|span| span.contains_key("elapsed_us")));
+
+    let insert = &spans[0];
+    assert!(insert["sql"].starts_with("INSERT INTO \"users\""));
+    assert_eq!(insert["binds"], "2");
+    assert_eq!(insert["rows"], "1");
+    assert_eq!(insert["cache_hit"], "false");
+
+    let (first, second) = (&spans[1], &spans[2]);
+    assert!(first["sql"].starts_with("SELECT \"users\".\"name\""));
+    assert_eq!(first["binds"], "1");
+    assert_eq!(first["rows"], "1");
+    assert_eq!(first["cache_hit"], "false");
+    assert_eq!(second["sql"], first["sql"]);
+    assert_eq!(second["cache_hit"], "true");
+
+    let batch = &spans[3];
+    assert_eq!(batch["sql"], "SELECT 1; SELECT 2");
+    assert!(!batch.contains_key("cache_hit"));
+}
+
+#[test]
+fn test_errors_are_span_events() {
+    let recorder = Recorder::default();
+    let mut conn = setup_basic_connection();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        users::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap_err();
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(spans.len(), 1);
+    assert!(!spans[0].contains_key("rows"));
+    assert!(spans[0]["error"].contains("users"));
+
+    assert_eq!(events.len(), 1);
+    let (span, fields) = &events[0];
+    assert_eq!(*span, Some(0));
+    assert_eq!(fields["message"], "DuckDB query failed");
+    assert_eq!(fields["level"], "DEBUG");
+    assert!(fields["error"].contains("users"));
+}
+
+#[test]
+fn test_handled_errors_are_not_events() {
+    let recorder = Recorder::default();
+    let conn = setup_basic_connection();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let error = conn
+            .as_ref()
+            .execute_batch("SELECT * FROM missing")
+            .map_diesel_error();
+        assert!(error.is_err());
+    });
+
+    assert!(recorder.spans.lock().unwrap().is_empty());
+    assert!(recorder.events.lock().unwrap().is_empty());
+}
@@ -0,0 +1,157 @@
+use diesel::connection::{SimpleConnection, TransactionManager};
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::BigInt;
+
+use super::setup_basic_connection;
+use crate::{DuckDbConnection, DuckDbErrorExt, DuckDbErrorKind, DuckDbTransactionManager};
+
+fn setup_items() -> DuckDbConnection {
+    let mut conn = setup_basic_connection();
+    conn.batch_execute("CREATE TABLE items (id INTEGER)")
+        .unwrap();
+    conn
+}
+
+fn item_count(conn: &mut DuckDbConnection) -> i64 {
+    diesel::select(sql::<BigInt>("(SELECT count(*) FROM items)"))
+        .get_result(conn)
+        .unwrap()
+}
+
+fn depth(conn: &mut DuckDbConnection) -> Option<u32> {
+    DuckDbTransactionManager::transaction_manager_status_mut(conn)
+        .transaction_depth()
+        .unwrap()
+        .map(|depth| depth.get())
+}
+
+#[test]
+fn test_nested_transactions_commit_together() {
+    let mut conn = setup_items();
+
+    conn.transaction(|conn| {
+        conn.batch_execute("INSERT INTO items VALUES (1)")?;
+        conn.transaction(|conn| {
+            assert_eq!(depth(conn), Some(2));
+            conn.batch_execute("INSERT INTO items VALUES (2)")
+        })?;
+        assert_eq!(depth(conn), Some(1));
+        Ok::<_, DieselError>(())
+    })
+    .unwrap();
+
+    assert_eq!(depth(&mut conn), None);
+    assert_eq!(item_count(&mut conn), 2);
+}
+
+#[test]
+fn test_nested_rollback_fails_outer_commit() {
+    let mut conn = setup_items();
+
+    let result = conn.transaction(|conn| {
+        conn.batch_execute("INSERT INTO items VALUES (1)")?;
+        let nested = conn.transaction(|conn| {
+            conn.batch_execute("INSERT INTO items VALUES (2)")?;
+            Err::<(), _>(DieselError::RollbackTransaction)
+        });
+        assert!(matches!(nested, Err(DieselError::RollbackTransaction)));
+        Ok::<_, DieselError>(())
+    });
+
+    let error = result.unwrap_err();
+    assert_eq!(
+        error.duckdb_error_kind(),
+        Some(DuckDbErrorKind::Transaction)
+    );
+    assert!(error
+        .to_string()
+        .contains("nested transaction was rolled back"));
+
+    // Nothing of the outer transaction was committed and the connection is usable
+    assert_eq!(depth(&mut conn), None);
+    assert_eq!(item_count(&mut conn), 0);
+    conn.transaction(|conn| conn.batch_execute("INSERT INTO items VALUES (3)"))
+        .unwrap();
+    assert_eq!(item_count(&mut conn), 1);
+}
+
+#[test]
+fn test_nested_error_rolls_back_outer_transaction() {
+    let mut conn = setup_items();
+
+    let result = conn.transaction(|conn| {
+        conn.batch_execute("INSERT INTO items VALUES (1)")?;
+        conn.transaction(|conn| conn.batch_execute("INSERT INTO missing VALUES (2)"))
+    });
+
+    assert_eq!(
+        result.unwrap_err().duckdb_error_kind(),
+        Some(DuckDbErrorKind::Catalog)
+    );
+    assert_eq!(depth(&mut conn), None);
+    assert_eq!(item_count(&mut conn), 0);
+}
+
+#[test]
+fn test_test_transaction_with_nested_transactions() {
+    let mut conn = setup_items();
+
+    conn.test_transaction::<_, DieselError, _>(|conn| {
+        conn.transaction(|conn| conn.batch_execute("INSERT INTO items VALUES (1)"))?;
+        assert_eq!(depth(conn), Some(1));
+        assert_eq!(item_count(conn), 1);
+        Ok(())
+    });
+
+    assert_eq!(depth(&mut conn), None);
+    assert_eq!(item_count(&mut conn), 0);
+}
+
+#[test]
+fn test_begin_test_transaction() {
+    let path = super::temp_database_path("begin_test_transaction");
+    let path = path.to_str().unwrap();
+    {
+        let mut conn = DuckDbConnection::establish(path).unwrap();
+        conn.batch_execute("CREATE TABLE items (id INTEGER)")
+            .unwrap();
+        conn.begin_test_transaction().unwrap();
+        conn.batch_execute("INSERT INTO items VALUES (1)").unwrap();
+        assert_eq!(item_count(&mut conn), 1);
+    }
+
+    let mut conn = DuckDbConnection::establish(path).unwrap();
+    assert_eq!(item_count(&mut conn), 0);
+}
+
+#[test]
+fn test_read_only_transaction() {
+    let mut conn = setup_items();
+    conn.batch_execute("INSERT INTO items VALUES (1)").unwrap();
+
+    let count = conn
+        .read_only_transaction(|conn| Ok::<_, DieselError>(item_count(conn)))
+        .unwrap();
+    assert_eq!(count, 1);
+
+    let result =
+        conn.read_only_transaction(|conn| conn.batch_execute("INSERT INTO items VALUES (2)"));
+    assert!(result.is_err());
+    assert_eq!(depth(&mut conn), None);
+    assert_eq!(item_count(&mut conn), 1);
+
+    // Only the next transaction is read-only
+    conn.transaction(|conn| conn.batch_execute("INSERT INTO items VALUES (2)"))
+        .unwrap();
+    assert_eq!(item_count(&mut conn), 2);
+}
+
+#[test]
+fn test_read_only_transaction_inside_transaction() {
+    let mut conn = setup_items();
+
+    let result = conn.transaction(|conn| conn.read_only_transaction(|_| Ok::<_, DieselError>(())));
+    assert!(matches!(result, Err(DieselError::AlreadyInTransaction)));
+}
@@ -0,0 +1,90 @@
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+use super::schema::users;
+use super::{setup_basic_connection, setup_users_table};
+
+#[test]
+fn test_clone_shares_in_memory_database() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+
+    let mut clone = conn.try_clone().unwrap();
+    clone
+        .batch_execute("INSERT INTO users (id, name) VALUES (1, 'Alice')")
+        .unwrap();
+
+    let names = users::table
+        .select(users::name)
+        .load::<Option<String>>(&mut conn)
+        .unwrap();
+    assert_eq!(names, vec![Some("Alice".to_string())]);
+}
+
+#[test]
+fn test_clones_query_in_parallel() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    conn.batch_execute(
+        "INSERT INTO users (id, name, age) SELECT i, 'user' || i, i % 50 FROM range(1, 1001) t(i)",
+    )
+    .unwrap();
+
+    let handles = (0..4)
+        .map(|worker| {
+            let mut clone = conn.try_clone().unwrap();
+            std::thread::spawn(move || {
+                users::table
+                    .filter(users::age.eq(worker))
+                    .count()
+                    .get_result::<i64>(&mut clone)
+                    .unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 20);
+    }
+}
+
+#[test]
+fn test_clone_has_own_transaction_state() {
+    let mut conn = setup_basic_connection();
+    setup_users_table(&mut conn);
+    let mut clone = conn.try_clone().unwrap();
+
+    conn.transaction::<_, DieselError, _>(|conn| {
+        diesel::insert_into(users::table)
+            .values((users::id.eq(1), users::name.eq("Alice")))
+            .execute(conn)?;
+
+        // The clone is outside the transaction and does not see the uncommitted row
+        let count: i64 = users::table.count().get_result(&mut clone)?;
+        assert_eq!(count, 0);
+        clone.transaction::<_, DieselError, _>(|_| Ok(()))
+    })
+    .unwrap();
+
+    let count: i64 = users::table.count().get_result(&mut clone).unwrap();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn test_clone_keeps_read_only_mode() {
+    let path = super::temp_database_path("try_clone_read_only");
+    let path = path.to_str().unwrap();
+    {
+        let mut writer = crate::DuckDbConnection::establish(path).unwrap();
+        setup_users_table(&mut writer);
+    }
+
+    let reader = crate::DuckDbConnection::builder(path)
+        .read_only()
+        .establish()
+        .unwrap();
+    let mut clone = reader.try_clone().unwrap();
+    assert!(clone.is_read_only());
+    assert!(diesel::delete(users::table).execute(&mut clone).is_err());
+}
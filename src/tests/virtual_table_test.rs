@@ -0,0 +1,279 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use duckdb::types::{TimeUnit, Value};
+
+use super::schema::users;
+use super::{
+    insert_basic_users, setup_basic_connection, setup_users_table, setup_users_with_basic_data,
+    temp_database_path,
+};
+use crate::{DuckDbConnection, VirtualColumnType, VirtualTable};
+
+diesel::table! {
+    sessions (user_id) {
+        user_id -> Integer,
+        device -> Nullable<Text>,
+        started -> Date,
+        last_seen -> Nullable<Timestamp>,
+    }
+}
+
+diesel::joinable!(sessions -> users (user_id));
+diesel::allow_tables_to_appear_in_same_query!(sessions, users);
+
+struct Session {
+    user_id: i32,
+    device: Option<&'static str>,
+    started: &'static str,
+}
+
+impl VirtualTable for Session {
+    fn columns() -> Vec<(&'static str, VirtualColumnType)> {
+        vec![
+            ("user_id", VirtualColumnType::Integer),
+            ("device", VirtualColumnType::Text),
+            ("started", VirtualColumnType::Date),
+            ("last_seen", VirtualColumnType::Timestamp),
+        ]
+    }
+
+    fn values(&self) -> Vec<Value> {
+        let started = NaiveDate::parse_from_str(self.started, "%Y-%m-%d").unwrap();
+        let days = (started - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+        let last_seen = started.and_hms_opt(8, 30, 0).unwrap();
+        vec![
+            self.user_id.into(),
+            self.device.map(String::from).into(),
+            Value::Date32(days as i32),
+            Value::Timestamp(
+                TimeUnit::Microsecond,
+                last_seen.and_utc().timestamp_micros(),
+            ),
+        ]
+    }
+}
+
+fn sessions() -> Vec<Session> {
+    vec![
+        Session {
+            user_id: 1,
+            device: Some("laptop"),
+            started: "2025-07-07",
+        },
+        Session {
+            user_id: 2,
+            device: None,
+            started: "2025-07-08",
+        },
+        Session {
+            user_id: 3,
+            device: Some("phone"),
+            started: "2025-07-09",
+        },
+    ]
+}
+
+#[test]
+fn test_query_registered_rows() {
+    let mut conn = setup_basic_connection();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+
+    let rows = sessions::table
+        .filter(sessions::user_id.gt(1))
+        .order(sessions::user_id)
+        .load::<(i32, Option<String>, NaiveDate, Option<NaiveDateTime>)>(&mut conn)
+        .unwrap();
+    let started = NaiveDate::from_ymd_opt(2025, 7, 9).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].1, None);
+    assert_eq!(
+        rows[1],
+        (
+            3,
+            Some("phone".to_string()),
+            started,
+            Some(started.and_hms_opt(8, 30, 0).unwrap())
+        )
+    );
+}
+
+#[test]
+fn test_join_with_table() {
+    let mut conn = setup_users_with_basic_data();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+
+    let rows = sessions::table
+        .inner_join(users::table)
+        .filter(users::age.ge(30))
+        .select((users::name, sessions::device))
+        .order(users::id)
+        .load::<(Option<String>, Option<String>)>(&mut conn)
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            (Some("John Doe".to_string()), Some("laptop".to_string())),
+            (Some("Bob Johnson".to_string()), Some("phone".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn test_more_rows_than_a_chunk() {
+    let mut conn = setup_basic_connection();
+    let rows = (0..5000).map(|user_id| Session {
+        user_id,
+        device: None,
+        started: "2025-07-07",
+    });
+    conn.register_virtual_table("sessions", rows).unwrap();
+
+    let (count, sum) = sessions::table
+        .select((
+            diesel::dsl::count_star(),
+            diesel::dsl::sum(sessions::user_id),
+        ))
+        .get_result::<(i64, Option<i64>)>(&mut conn)
+        .unwrap();
+    assert_eq!(count, 5000);
+    assert_eq!(sum, Some((0..5000).sum()));
+}
+
+#[test]
+fn test_register_again_replaces_rows() {
+    let mut conn = setup_basic_connection();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+    conn.register_virtual_table("sessions", sessions().into_iter().take(1))
+        .unwrap();
+
+    let ids = sessions::table
+        .select(sessions::user_id)
+        .load::<i32>(&mut conn)
+        .unwrap();
+    assert_eq!(ids, [1]);
+}
+
+#[test]
+fn test_unregister() {
+    let mut conn = setup_basic_connection();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+    conn.unregister_virtual_table("sessions").unwrap();
+
+    assert!(sessions::table
+        .select(sessions::user_id)
+        .load::<i32>(&mut conn)
+        .is_err());
+    // unknown names are ignored
+    conn.unregister_virtual_table("sessions").unwrap();
+}
+
+#[test]
+fn test_visible_to_registering_connection_only() {
+    let mut conn = setup_basic_connection();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+    let mut other = conn.try_clone().unwrap();
+
+    assert!(sessions::table
+        .select(sessions::user_id)
+        .load::<i32>(&mut other)
+        .is_err());
+}
+
+#[test]
+fn test_rows_of_another_connection_are_rejected() {
+    let mut conn = setup_basic_connection();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+    let key = diesel::dsl::sql::<diesel::sql_types::Text>(
+        "SELECT regexp_extract(sql, '(\\d+)\\)', 1) FROM duckdb_views() WHERE view_name = 'sessions'",
+    )
+    .get_result::<String>(&mut conn)
+    .unwrap();
+
+    // Another connection knowing the key can't read the rows, with its own
+    // token or a guessed one
+    let mut other = conn.try_clone().unwrap();
+    other
+        .register_virtual_table("more_sessions", sessions())
+        .unwrap();
+    for owner in ["getvariable('__diesel_duckdb_connection')", "'guess'"] {
+        let error = other
+            .batch_execute(&format!(
+                "SELECT * FROM __diesel_duckdb_rows({}, {})",
+                owner, key
+            ))
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("data of another connection"),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn test_table_function_is_shared() {
+    let mut conn = setup_basic_connection();
+    let mut other = conn.try_clone().unwrap();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+    conn.register_virtual_table("more_sessions", sessions())
+        .unwrap();
+    other
+        .register_virtual_table("sessions", sessions())
+        .unwrap();
+
+    let functions = diesel::dsl::sql::<diesel::sql_types::BigInt>(
+        "SELECT count(*) FROM duckdb_functions() WHERE starts_with(function_name, '__diesel_duckdb')",
+    )
+    .get_result::<i64>(&mut conn);
+    assert_eq!(functions, Ok(1));
+    let count = sessions::table.count().get_result::<i64>(&mut other);
+    assert_eq!(count, Ok(3));
+}
+
+#[test]
+fn test_invalid_value() {
+    struct Bad;
+
+    impl VirtualTable for Bad {
+        fn columns() -> Vec<(&'static str, VirtualColumnType)> {
+            vec![("user_id", VirtualColumnType::Integer)]
+        }
+
+        fn values(&self) -> Vec<Value> {
+            vec![Value::Text("one".to_string())]
+        }
+    }
+
+    let mut conn = setup_basic_connection();
+    conn.register_virtual_table("bad", [Bad]).unwrap();
+
+    let error = conn.batch_execute("SELECT * FROM bad").unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("Cannot read DuckDB VARCHAR value as i32"));
+}
+
+#[test]
+fn test_read_only_connection() {
+    let path = temp_database_path("virtual_table_read_only");
+    let path = path.to_str().unwrap();
+    {
+        let mut conn = DuckDbConnection::establish(path).unwrap();
+        setup_users_table(&mut conn);
+        insert_basic_users(&mut conn);
+    }
+
+    let mut conn = DuckDbConnection::builder(path)
+        .read_only()
+        .establish()
+        .unwrap();
+    conn.register_virtual_table("sessions", sessions()).unwrap();
+
+    let count = sessions::table
+        .inner_join(users::table)
+        .count()
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(count, 3);
+}
@@ -0,0 +1,140 @@
+// Spans for queries when the `tracing` feature is enabled
+//
+// Every query runs inside a `duckdb.query` span. The span is created before
+// the SQL is known and the fields are recorded as the query progresses, on
+// the innermost query span of the thread so the connection code doesn't have
+// to pass it around. A failed query records its error on its span when it
+// finishes. Without the feature all of this compiles to nothing.
+
+#[cfg(feature = "tracing")]
+use std::cell::RefCell;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    // Query spans entered on this thread, innermost last. Kept separately from
+    // `Span::current` so fields are recorded even when the subscriber doesn't
+    // track the current span.
+    static QUERY_SPANS: RefCell<Vec<tracing::Span>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(feature = "tracing")]
+fn with_query_span(f: impl FnOnce(&tracing::Span)) {
+    QUERY_SPANS.with(|spans| {
+        if let Some(span) = spans.borrow().last() {
+            f(span);
+        }
+    });
+}
+
+// Removes the innermost query span of the thread when dropped, so a panic
+// inside the span doesn't leave it behind
+#[cfg(feature = "tracing")]
+struct EnteredQuerySpan;
+
+#[cfg(feature = "tracing")]
+impl EnteredQuerySpan {
+    fn enter(span: &tracing::Span) -> Self {
+        QUERY_SPANS.with(|spans| spans.borrow_mut().push(span.clone()));
+        EnteredQuerySpan
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for EnteredQuerySpan {
+    fn drop(&mut self) {
+        QUERY_SPANS.with(|spans| spans.borrow_mut().pop());
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) struct QuerySpan {
+    span: tracing::Span,
+    start: Instant,
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct QuerySpan;
+
+impl QuerySpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn new() -> Self {
+        use tracing::field::Empty;
+
+        Self {
+            span: tracing::debug_span!(
+                "duckdb.query",
+                sql = Empty,
+                binds = Empty,
+                cache_hit = Empty,
+                rows = Empty,
+                elapsed_us = Empty,
+                error = Empty,
+            ),
+            start: Instant::now(),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn new() -> Self {
+        QuerySpan
+    }
+
+    /// Run `f` inside the span and record the elapsed time and the error
+    /// the query failed with
+    pub(crate) fn in_scope<R>(
+        self,
+        f: impl FnOnce() -> diesel::QueryResult<R>,
+    ) -> diesel::QueryResult<R> {
+        #[cfg(feature = "tracing")]
+        {
+            let result = {
+                let _entered = EnteredQuerySpan::enter(&self.span);
+                self.span.in_scope(f)
+            };
+            let elapsed = self.start.elapsed().as_micros() as u64;
+            self.span.record("elapsed_us", elapsed);
+            if let Err(error) = &result {
+                self.span.record("error", tracing::field::display(error));
+                self.span
+                    .in_scope(|| tracing::debug!(error = %error, "DuckDB query failed"));
+            }
+            result
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        f()
+    }
+}
+
+/// Record the SQL of the current query and whether its statement was cached before
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn record_statement(sql: &str, cache_hit: Option<bool>) {
+    #[cfg(feature = "tracing")]
+    {
+        with_query_span(|span| {
+            span.record("sql", sql);
+            if let Some(cache_hit) = cache_hit {
+                span.record("cache_hit", cache_hit);
+            }
+        });
+    }
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn record_binds(binds: usize) {
+    #[cfg(feature = "tracing")]
+    with_query_span(|span| {
+        span.record("binds", binds);
+    });
+}
+
+/// Record the number of rows returned or affected by the current query
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn record_rows(rows: usize) {
+    #[cfg(feature = "tracing")]
+    with_query_span(|span| {
+        span.record("rows", rows);
+    });
+}
@@ -0,0 +1,169 @@
+//! Transactions without savepoints
+//!
+//! DuckDB has no savepoints, so nested transactions are flattened into the
+//! outermost one: only the outermost transaction sends `BEGIN` and `COMMIT`.
+//! Rolling back a nested transaction can't undo just its own changes, so it
+//! marks the whole transaction for rollback instead, and committing the
+//! outermost transaction then rolls it back and fails.
+
+use std::num::NonZeroU32;
+
+use diesel::connection::{
+    Connection, InstrumentationEvent, SimpleConnection, TransactionDepthChange, TransactionManager,
+    TransactionManagerStatus, ValidTransactionManagerStatus,
+};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::QueryResult;
+
+use crate::{DuckDbConnection, DuckDbErrorInformation};
+
+/// Transaction manager of [`DuckDbConnection`]
+#[derive(Debug, Default)]
+pub struct DuckDbTransactionManager {
+    status: TransactionManagerStatus,
+    // Begin the next outermost transaction with `BEGIN TRANSACTION READ ONLY`
+    read_only: bool,
+}
+
+impl DuckDbTransactionManager {
+    fn state(conn: &mut DuckDbConnection) -> QueryResult<&mut ValidTransactionManagerStatus> {
+        conn.transaction_state().status.transaction_state()
+    }
+
+    // The transaction has ended, whatever diesel knew about it
+    fn reset(conn: &mut DuckDbConnection) {
+        conn.transaction_state().status = TransactionManagerStatus::default();
+    }
+}
+
+impl TransactionManager<DuckDbConnection> for DuckDbTransactionManager {
+    type TransactionStateData = Self;
+
+    fn begin_transaction(conn: &mut DuckDbConnection) -> QueryResult<()> {
+        let depth = Self::state(conn)?.transaction_depth();
+        let new_depth = depth
+            .map_or(Some(NonZeroU32::MIN), |depth| depth.checked_add(1))
+            .ok_or_else(|| {
+                DieselError::QueryBuilderError("Transaction depth is too large".into())
+            })?;
+        conn.instrumentation()
+            .on_connection_event(InstrumentationEvent::begin_transaction(new_depth));
+
+        if depth.is_none() {
+            let sql = if std::mem::take(&mut conn.transaction_state().read_only) {
+                "BEGIN TRANSACTION READ ONLY"
+            } else {
+                "BEGIN"
+            };
+            conn.batch_execute(sql)?;
+        }
+        Self::state(conn)?.change_transaction_depth(TransactionDepthChange::IncreaseDepth)
+    }
+
+    fn rollback_transaction(conn: &mut DuckDbConnection) -> QueryResult<()> {
+        let depth = Self::state(conn)?
+            .transaction_depth()
+            .ok_or(DieselError::NotInTransaction)?;
+        conn.instrumentation()
+            .on_connection_event(InstrumentationEvent::rollback_transaction(depth));
+
+        if depth.get() > 1 {
+            let state = Self::state(conn)?;
+            if let Some(in_transaction) = &mut state.in_transaction {
+                in_transaction.requires_rollback_maybe_up_to_top_level = true;
+            }
+            return state.change_transaction_depth(TransactionDepthChange::DecreaseDepth);
+        }
+
+        match conn.batch_execute("ROLLBACK") {
+            Ok(()) => {
+                Self::reset(conn);
+                Ok(())
+            }
+            // DuckDB already ended the transaction after an error
+            Err(DieselError::DatabaseError(_, info))
+                if info.message().contains("no transaction is active") =>
+            {
+                Self::reset(conn);
+                Ok(())
+            }
+            Err(error) => {
+                conn.transaction_state().status.set_in_error();
+                Err(error)
+            }
+        }
+    }
+
+    fn commit_transaction(conn: &mut DuckDbConnection) -> QueryResult<()> {
+        let in_transaction = Self::state(conn)?
+            .in_transaction
+            .as_ref()
+            .ok_or(DieselError::NotInTransaction)?;
+        let depth = in_transaction.transaction_depth;
+        let nested_rollback = in_transaction.requires_rollback_maybe_up_to_top_level;
+        conn.instrumentation()
+            .on_connection_event(InstrumentationEvent::commit_transaction(depth));
+
+        if depth.get() > 1 {
+            return Self::state(conn)?
+                .change_transaction_depth(TransactionDepthChange::DecreaseDepth);
+        }
+        if nested_rollback {
+            Self::rollback_transaction(conn)?;
+            return Err(nested_rollback_error());
+        }
+
+        let result = conn.batch_execute("COMMIT");
+        if result.is_err() {
+            // DuckDB ends a transaction whose commit failed, make sure it did
+            let _ = conn.batch_execute("ROLLBACK");
+        }
+        Self::reset(conn);
+        result
+    }
+
+    fn transaction_manager_status_mut(
+        conn: &mut DuckDbConnection,
+    ) -> &mut TransactionManagerStatus {
+        &mut conn.transaction_state().status
+    }
+}
+
+impl DuckDbConnection {
+    /// Run `f` in a transaction begun with `BEGIN TRANSACTION READ ONLY`
+    ///
+    /// DuckDB rejects writes inside the transaction. Fails with
+    /// [`DieselError::AlreadyInTransaction`] inside another transaction, as
+    /// that one can't be made read-only.
+    pub fn read_only_transaction<T, E, F>(&mut self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Self) -> Result<T, E>,
+        E: From<DieselError>,
+    {
+        if DuckDbTransactionManager::state(self)?
+            .transaction_depth()
+            .is_some()
+        {
+            return Err(DieselError::AlreadyInTransaction.into());
+        }
+        self.transaction_state().read_only = true;
+        DuckDbTransactionManager::transaction(self, f)
+    }
+}
+
+fn nested_rollback_error() -> DieselError {
+    DieselError::DatabaseError(
+        DatabaseErrorKind::Unknown,
+        Box::new(DuckDbErrorInformation {
+            error_message: "TransactionContext Error: A nested transaction was rolled back, \
+                DuckDB has no savepoints so the whole transaction was rolled back"
+                .to_string(),
+            table_name: None,
+            column_name: None,
+            constraint_name: None,
+            statement_position: None,
+            details: None,
+            hint: None,
+        }),
+    )
+}
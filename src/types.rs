@@ -1,6 +1,6 @@
 use diesel::{deserialize::FromSql, serialize::IsNull, sql_types::*};
-use duckdb::types::ValueRef;
 
+use crate::value::{raw_value, FromDuckDbValue};
 use crate::DuckDb;
 
 macro_rules! duckdb_to_sql_diesel {
@@ -24,15 +24,8 @@ macro_rules! sql_diesel_to_duckdb {
             fn from_sql(
                 duckdb_value: <DuckDb as diesel::backend::Backend>::RawValue<'_>,
             ) -> diesel::deserialize::Result<Self> {
-                use duckdb::types::ToSqlOutput;
-
-                let value_ref = match duckdb_value {
-                    ToSqlOutput::Borrowed(v) => v,
-                    ToSqlOutput::Owned(ref v) => ValueRef::from(v),
-                    _ => unimplemented!(),
-                };
-
-                let value = duckdb::types::FromSql::column_result(value_ref)?;
+                let value = raw_value(&duckdb_value)?;
+                let value = <$rust_type as FromDuckDbValue>::from_duckdb_value(&value)?;
                 Ok(value)
             }
         }
@@ -0,0 +1,315 @@
+// Conversions from raw DuckDB values into Rust types
+//
+// The `FromSql` impls of the `duckdb` crate unwrap on out-of-range casts and
+// are unimplemented for nested values, so every diesel `FromSql` impl of this
+// backend goes through `FromDuckDbValue` instead. Every failure is reported as
+// a `FromDuckDbValueError`, never as a panic.
+//
+// Values are widened to the requested Rust type whenever nothing is lost:
+//
+// - integers: any DuckDB integer (including HUGEINT and unsigned types) and
+//   DECIMALs without fractional digits, if the value fits
+// - floats: FLOAT for `f32`, FLOAT and DOUBLE for `f64`, plus integers the
+//   float type holds exactly (up to 2^24 for `f32`, 2^53 for `f64`) and
+//   DECIMALs that round-trip, i.e. the nearest float reads back as the same
+//   DECIMAL (`0.1` does, a DECIMAL with more significant digits than the float
+//   type carries does not)
+// - timestamps: TIMESTAMP in any unit, and DATE as midnight of that day
+//
+// Narrowing a value that does not fit is an `OutOfRange` error, a DECIMAL
+// with fractional digits read as an integer or one that doesn't round-trip
+// through a float an `Invalid` error. Converting
+// between unrelated types (e.g. DOUBLE to an integer, VARCHAR to a date or
+// an integer to `bool`) is an `InvalidType` error, text is never parsed.
+
+use std::borrow::Cow;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use duckdb::types::{TimeUnit, ToSqlOutput, Value, ValueRef};
+use rust_decimal::Decimal;
+
+use crate::error::FromDuckDbValueError;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// Fallible conversion from an owned DuckDB value
+pub(crate) trait FromDuckDbValue: Sized {
+    /// Name of the Rust type, used in error messages
+    const TARGET: &'static str;
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError>;
+}
+
+/// Get the DuckDB value out of the raw value handed to diesel
+pub(crate) fn raw_value<'a>(
+    raw: &'a ToSqlOutput<'_>,
+) -> Result<Cow<'a, Value>, FromDuckDbValueError> {
+    match raw {
+        ToSqlOutput::Owned(value) => Ok(Cow::Borrowed(value)),
+        // `ValueRef::to_owned` panics on invalid UTF-8, so check it here
+        ToSqlOutput::Borrowed(ValueRef::Text(bytes)) => std::str::from_utf8(bytes)
+            .map(|text| Cow::Owned(Value::Text(text.to_owned())))
+            .map_err(|e| FromDuckDbValueError::Invalid {
+                target: "text",
+                message: e.to_string(),
+            }),
+        ToSqlOutput::Borrowed(value) => Ok(Cow::Owned(value.to_owned())),
+        _ => Err(FromDuckDbValueError::UnsupportedRawValue),
+    }
+}
+
+/// SQL name of the type of a DuckDB value
+///
+/// `Value::data_type` is unimplemented for nested values, so this is used for
+/// error reporting instead.
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "NULL",
+        Value::Boolean(_) => "BOOLEAN",
+        Value::TinyInt(_) => "TINYINT",
+        Value::SmallInt(_) => "SMALLINT",
+        Value::Int(_) => "INTEGER",
+        Value::BigInt(_) => "BIGINT",
+        Value::HugeInt(_) => "HUGEINT",
+        Value::UTinyInt(_) => "UTINYINT",
+        Value::USmallInt(_) => "USMALLINT",
+        Value::UInt(_) => "UINTEGER",
+        Value::UBigInt(_) => "UBIGINT",
+        Value::Float(_) => "FLOAT",
+        Value::Double(_) => "DOUBLE",
+        Value::Decimal(_) => "DECIMAL",
+        Value::Timestamp(..) => "TIMESTAMP",
+        Value::Text(_) => "VARCHAR",
+        Value::Blob(_) => "BLOB",
+        Value::Date32(_) => "DATE",
+        Value::Time64(..) => "TIME",
+        Value::Interval { .. } => "INTERVAL",
+        Value::List(_) => "LIST",
+        Value::Enum(_) => "ENUM",
+        Value::Struct(_) => "STRUCT",
+        Value::Array(_) => "ARRAY",
+        Value::Map(_) => "MAP",
+        Value::Union(_) => "UNION",
+    }
+}
+
+fn invalid_type<T: FromDuckDbValue>(value: &Value) -> FromDuckDbValueError {
+    FromDuckDbValueError::InvalidType {
+        target: T::TARGET,
+        found: type_name(value),
+    }
+}
+
+fn out_of_range<T: FromDuckDbValue>(value: impl ToString) -> FromDuckDbValueError {
+    FromDuckDbValueError::OutOfRange {
+        target: T::TARGET,
+        value: value.to_string(),
+    }
+}
+
+fn invalid<T: FromDuckDbValue>(message: impl ToString) -> FromDuckDbValueError {
+    FromDuckDbValueError::Invalid {
+        target: T::TARGET,
+        message: message.to_string(),
+    }
+}
+
+/// Any DuckDB integer value, widened to `i128`
+fn integer_value(value: &Value) -> Option<i128> {
+    match *value {
+        Value::TinyInt(v) => Some(v.into()),
+        Value::SmallInt(v) => Some(v.into()),
+        Value::Int(v) => Some(v.into()),
+        Value::BigInt(v) => Some(v.into()),
+        Value::HugeInt(v) => Some(v),
+        Value::UTinyInt(v) => Some(v.into()),
+        Value::USmallInt(v) => Some(v.into()),
+        Value::UInt(v) => Some(v.into()),
+        Value::UBigInt(v) => Some(v.into()),
+        _ => None,
+    }
+}
+
+macro_rules! from_duckdb_integer {
+    ($($rust_type:ty),*) => {
+        $(
+            impl FromDuckDbValue for $rust_type {
+                const TARGET: &'static str = stringify!($rust_type);
+
+                fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+                    if let Some(i) = integer_value(value) {
+                        return <$rust_type>::try_from(i).map_err(|_| out_of_range::<Self>(i));
+                    }
+                    match value {
+                        Value::Decimal(d) => {
+                            let i = integral_decimal(d).ok_or_else(|| {
+                                invalid::<Self>(format!("{} has fractional digits", d))
+                            })?;
+                            <$rust_type>::try_from(i).map_err(|_| out_of_range::<Self>(d))
+                        }
+                        _ => Err(invalid_type::<Self>(value)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_duckdb_integer!(i8, i16, i32, i64);
+
+impl FromDuckDbValue for f64 {
+    const TARGET: &'static str = "f64";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        if let Some(i) = integer_value(value) {
+            return exact_float(i, F64_EXACT_INTEGER).ok_or_else(|| out_of_range::<Self>(i));
+        }
+        match value {
+            Value::Float(v) => Ok((*v).into()),
+            Value::Double(v) => Ok(*v),
+            Value::Decimal(d) => round_trip_decimal(d),
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for f32 {
+    const TARGET: &'static str = "f32";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        if let Some(i) = integer_value(value) {
+            return exact_float(i, F32_EXACT_INTEGER)
+                .map(|v| v as f32)
+                .ok_or_else(|| out_of_range::<Self>(i));
+        }
+        match value {
+            Value::Float(v) => Ok(*v),
+            Value::Decimal(d) => round_trip_decimal(d),
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for bool {
+    const TARGET: &'static str = "bool";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        match value {
+            Value::Boolean(b) => Ok(*b),
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for String {
+    const TARGET: &'static str = "String";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        match value {
+            Value::Text(text) | Value::Enum(text) => Ok(text.clone()),
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for Vec<u8> {
+    const TARGET: &'static str = "Vec<u8>";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        match value {
+            Value::Blob(bytes) => Ok(bytes.clone()),
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for NaiveDate {
+    const TARGET: &'static str = "NaiveDate";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        match value {
+            Value::Date32(days) => DateTime::from_timestamp(i64::from(*days) * 86_400, 0)
+                .map(|dt| dt.date_naive())
+                .ok_or_else(|| out_of_range::<Self>(days)),
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for NaiveTime {
+    const TARGET: &'static str = "NaiveTime";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        match value {
+            Value::Time64(unit, t) => {
+                let nanos = i128::from(*t) * nanos_per_unit(*unit);
+                let secs = u32::try_from(nanos.div_euclid(NANOS_PER_SECOND));
+                let frac = nanos.rem_euclid(NANOS_PER_SECOND) as u32;
+                secs.ok()
+                    .filter(|_| nanos >= 0)
+                    .and_then(|secs| NaiveTime::from_num_seconds_from_midnight_opt(secs, frac))
+                    .ok_or_else(|| out_of_range::<Self>(t))
+            }
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+impl FromDuckDbValue for NaiveDateTime {
+    const TARGET: &'static str = "NaiveDateTime";
+
+    fn from_duckdb_value(value: &Value) -> Result<Self, FromDuckDbValueError> {
+        match value {
+            Value::Date32(_) => {
+                NaiveDate::from_duckdb_value(value).map(|date| date.and_time(NaiveTime::MIN))
+            }
+            Value::Timestamp(unit, t) => {
+                let dt = match unit {
+                    TimeUnit::Second => DateTime::from_timestamp(*t, 0),
+                    TimeUnit::Millisecond => DateTime::from_timestamp_millis(*t),
+                    TimeUnit::Microsecond => DateTime::from_timestamp_micros(*t),
+                    TimeUnit::Nanosecond => Some(DateTime::from_timestamp_nanos(*t)),
+                };
+                dt.map(|dt| dt.naive_utc())
+                    .ok_or_else(|| out_of_range::<Self>(t))
+            }
+            _ => Err(invalid_type::<Self>(value)),
+        }
+    }
+}
+
+/// The value of a DECIMAL without fractional digits
+fn integral_decimal(d: &Decimal) -> Option<i128> {
+    let d = d.normalize();
+    (d.scale() == 0).then(|| d.mantissa())
+}
+
+/// The float nearest to a DECIMAL, if it reads back as the same DECIMAL
+fn round_trip_decimal<T>(d: &Decimal) -> Result<T, FromDuckDbValueError>
+where
+    T: FromDuckDbValue + std::str::FromStr + ToString,
+{
+    d.to_string()
+        .parse::<T>()
+        .ok()
+        .filter(|v| v.to_string().parse::<Decimal>().ok() == Some(*d))
+        .ok_or_else(|| invalid::<T>(format!("{} doesn't round-trip through {}", d, T::TARGET)))
+}
+
+/// Largest integer magnitudes `f32` and `f64` represent exactly
+const F32_EXACT_INTEGER: i128 = 1 << f32::MANTISSA_DIGITS;
+const F64_EXACT_INTEGER: i128 = 1 << f64::MANTISSA_DIGITS;
+
+/// Convert an integer to `f64` if the target float type holds it exactly
+fn exact_float(i: i128, limit: i128) -> Option<f64> {
+    (i.unsigned_abs() <= limit.unsigned_abs()).then_some(i as f64)
+}
+
+fn nanos_per_unit(unit: TimeUnit) -> i128 {
+    match unit {
+        TimeUnit::Second => NANOS_PER_SECOND,
+        TimeUnit::Millisecond => 1_000_000,
+        TimeUnit::Microsecond => 1_000,
+        TimeUnit::Nanosecond => 1,
+    }
+}
@@ -0,0 +1,298 @@
+//! Rust rows as tables, through DuckDB's table function API
+//!
+//! Every registration keeps its rows in the crate's registry and gets a
+//! temporary view, which a `table!` declaration of the same name refers to.
+//! The views read the rows through a single table function, taking the
+//! token of the connection and the registry key of the rows as arguments,
+//! so that other connections can't read them.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::QueryResult;
+use duckdb::core::{DataChunkHandle, FlatVector, Inserter, LogicalTypeHandle, LogicalTypeId};
+use duckdb::types::Value;
+use duckdb::vtab::{BindInfo, InitInfo, TableFunctionInfo, VTab};
+
+use crate::attach::quote_identifier;
+use crate::error::{FromDuckDbValueError, MapQueryError};
+use crate::registry::{self, Registration};
+use crate::value::FromDuckDbValue;
+use crate::DuckDbConnection;
+
+/// A row type that can be registered with [`DuckDbConnection::register_virtual_table`]
+///
+/// ```ignore
+/// struct Session {
+///     user_id: i32,
+///     device: Option<String>,
+/// }
+///
+/// impl VirtualTable for Session {
+///     fn columns() -> Vec<(&'static str, VirtualColumnType)> {
+///         vec![
+///             ("user_id", VirtualColumnType::Integer),
+///             ("device", VirtualColumnType::Text),
+///         ]
+///     }
+///
+///     fn values(&self) -> Vec<Value> {
+///         vec![self.user_id.into(), self.device.clone().into()]
+///     }
+/// }
+/// ```
+pub trait VirtualTable: Send + Sync + 'static {
+    /// Names and types of the columns
+    fn columns() -> Vec<(&'static str, VirtualColumnType)>;
+
+    /// Values of the columns of this row, in the order of [`columns`](Self::columns)
+    ///
+    /// `Value::Null` is NULL, any other value is converted to the type of its
+    /// column if nothing is lost, e.g. `Value::Int` for a `BigInt` or
+    /// `Value::Date32` for a `Timestamp`.
+    fn values(&self) -> Vec<Value>;
+}
+
+/// Type of a column of a [`VirtualTable`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtualColumnType {
+    Boolean,
+    TinyInt,
+    SmallInt,
+    Integer,
+    BigInt,
+    Float,
+    Double,
+    Text,
+    Blob,
+    Date,
+    Timestamp,
+}
+
+impl VirtualColumnType {
+    pub(crate) fn logical_type(self) -> LogicalTypeHandle {
+        LogicalTypeHandle::from(self.type_id())
+    }
+
+    pub(crate) fn type_id(self) -> LogicalTypeId {
+        match self {
+            Self::Boolean => LogicalTypeId::Boolean,
+            Self::TinyInt => LogicalTypeId::Tinyint,
+            Self::SmallInt => LogicalTypeId::Smallint,
+            Self::Integer => LogicalTypeId::Integer,
+            Self::BigInt => LogicalTypeId::Bigint,
+            Self::Float => LogicalTypeId::Float,
+            Self::Double => LogicalTypeId::Double,
+            Self::Text => LogicalTypeId::Varchar,
+            Self::Blob => LogicalTypeId::Blob,
+            Self::Date => LogicalTypeId::Date,
+            Self::Timestamp => LogicalTypeId::Timestamp,
+        }
+    }
+}
+
+impl DuckDbConnection {
+    /// Make `rows` queryable as the table `name` on this connection
+    ///
+    /// The rows are kept in memory and read by DuckDB when a query uses the
+    /// table, so they can be joined with the tables of the database without
+    /// inserting them first. Declare the table with `table!`, its columns as
+    /// in [`VirtualTable::columns`].
+    ///
+    /// The table is a temporary view of this connection, and also works on
+    /// read-only connections. Registering `name` again replaces the rows. The
+    /// view reads the rows through the table function `__diesel_duckdb_rows`,
+    /// which is registered with the database on first use and shared by all
+    /// of its connections.
+    pub fn register_virtual_table<R, I>(&mut self, name: &str, rows: I) -> QueryResult<()>
+    where
+        R: VirtualTable,
+        I: IntoIterator<Item = R>,
+    {
+        let rows: Box<dyn VirtualRows> = Box::new(rows.into_iter().collect::<Vec<R>>());
+        let registration = Registration::new(self.registry_owner()?, Arc::new(rows));
+
+        registry::register_once(self.as_ref(), ROWS_FUNCTION, || {
+            self.as_ref()
+                .register_table_function::<RowsFunction>(ROWS_FUNCTION)
+        })?;
+        let sql = format!(
+            "CREATE OR REPLACE TEMP VIEW {} AS SELECT * FROM {}({}, {})",
+            quote_identifier(name),
+            ROWS_FUNCTION,
+            registry::OWNER_ARGUMENT,
+            registration.key()
+        );
+        self.as_ref().execute_batch(&sql).map_query_error(&sql)?;
+
+        self.virtual_tables.insert(name.to_string(), registration);
+        Ok(())
+    }
+
+    /// Remove the table `name` registered with
+    /// [`register_virtual_table`](Self::register_virtual_table), and free its rows
+    ///
+    /// Does nothing if no such table is registered.
+    pub fn unregister_virtual_table(&mut self, name: &str) -> QueryResult<()> {
+        if self.virtual_tables.remove(name).is_some() {
+            let sql = format!("DROP VIEW IF EXISTS temp.{}", quote_identifier(name));
+            self.as_ref().execute_batch(&sql).map_query_error(&sql)?;
+        }
+        Ok(())
+    }
+}
+
+const ROWS_FUNCTION: &str = "__diesel_duckdb_rows";
+
+// The rows of a registered table, whatever their `VirtualTable` type
+trait VirtualRows: Send + Sync {
+    fn columns(&self) -> Vec<(&'static str, VirtualColumnType)>;
+
+    fn row_count(&self) -> usize;
+
+    fn values(&self, row: usize) -> Vec<Value>;
+}
+
+impl<R: VirtualTable> VirtualRows for Vec<R> {
+    fn columns(&self) -> Vec<(&'static str, VirtualColumnType)> {
+        R::columns()
+    }
+
+    fn row_count(&self) -> usize {
+        self.len()
+    }
+
+    fn values(&self, row: usize) -> Vec<Value> {
+        self[row].values()
+    }
+}
+
+struct RowsFunction;
+
+struct RowsBindData {
+    rows: Arc<Box<dyn VirtualRows>>,
+    types: Vec<VirtualColumnType>,
+}
+
+impl VTab for RowsFunction {
+    type InitData = AtomicUsize;
+    type BindData = RowsBindData;
+
+    fn bind(bind: &BindInfo) -> Result<Self::BindData, Box<dyn std::error::Error>> {
+        let rows = registry::lookup::<Box<dyn VirtualRows>>(
+            &bind.get_parameter(0).to_string(),
+            bind.get_parameter(1).to_int64(),
+        )?;
+
+        let columns = rows.columns();
+        for (name, column_type) in &columns {
+            bind.add_result_column(name, column_type.logical_type());
+        }
+        Ok(RowsBindData {
+            rows,
+            types: columns
+                .into_iter()
+                .map(|(_, column_type)| column_type)
+                .collect(),
+        })
+    }
+
+    fn init(_: &InitInfo) -> Result<Self::InitData, Box<dyn std::error::Error>> {
+        Ok(AtomicUsize::new(0))
+    }
+
+    fn func(
+        func: &TableFunctionInfo<Self>,
+        output: &mut DataChunkHandle,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let bind_data = func.get_bind_data();
+        let offset = func.get_init_data();
+        let mut vectors = (0..bind_data.types.len())
+            .map(|i| output.flat_vector(i))
+            .collect::<Vec<_>>();
+        let capacity = vectors.first().map_or(0, FlatVector::capacity);
+
+        let row_count = bind_data.rows.row_count();
+        let start = offset.load(Ordering::Relaxed).min(row_count);
+        let end = (start + capacity).min(row_count);
+        for (i, row) in (start..end).enumerate() {
+            let values = bind_data.rows.values(row);
+            if values.len() != bind_data.types.len() {
+                return Err(format!(
+                    "virtual table row has {} values for {} columns",
+                    values.len(),
+                    bind_data.types.len()
+                )
+                .into());
+            }
+            for ((vector, column_type), value) in
+                vectors.iter_mut().zip(&bind_data.types).zip(&values)
+            {
+                write_value(vector, i, *column_type, value)?;
+            }
+        }
+        offset.store(end, Ordering::Relaxed);
+        output.set_len(end - start);
+        Ok(())
+    }
+
+    fn parameters() -> Option<Vec<LogicalTypeHandle>> {
+        Some(vec![
+            LogicalTypeHandle::from(LogicalTypeId::Varchar),
+            LogicalTypeHandle::from(LogicalTypeId::Bigint),
+        ])
+    }
+}
+
+/// Write `value` into `row` of a vector of `column_type`, `Value::Null` as NULL
+pub(crate) fn write_value(
+    vector: &mut FlatVector,
+    row: usize,
+    column_type: VirtualColumnType,
+    value: &Value,
+) -> Result<(), FromDuckDbValueError> {
+    if *value == Value::Null {
+        vector.set_null(row);
+        return Ok(());
+    }
+    match column_type {
+        VirtualColumnType::Boolean => set(vector, row, bool::from_duckdb_value(value)?),
+        VirtualColumnType::TinyInt => set(vector, row, i8::from_duckdb_value(value)?),
+        VirtualColumnType::SmallInt => set(vector, row, i16::from_duckdb_value(value)?),
+        VirtualColumnType::Integer => set(vector, row, i32::from_duckdb_value(value)?),
+        VirtualColumnType::BigInt => set(vector, row, i64::from_duckdb_value(value)?),
+        VirtualColumnType::Float => set(vector, row, f32::from_duckdb_value(value)?),
+        VirtualColumnType::Double => set(vector, row, f64::from_duckdb_value(value)?),
+        VirtualColumnType::Text => {
+            let text = String::from_duckdb_value(value)?;
+            // DuckDB's C API takes strings NUL terminated
+            if text.contains('\0') {
+                return Err(FromDuckDbValueError::Invalid {
+                    target: String::TARGET,
+                    message: "text contains a NUL character".to_string(),
+                });
+            }
+            vector.insert(row, text.as_str());
+        }
+        VirtualColumnType::Blob => {
+            vector.insert(row, Vec::<u8>::from_duckdb_value(value)?.as_slice())
+        }
+        VirtualColumnType::Date => {
+            // days since the Unix epoch, which fit into i32 for every NaiveDate
+            let date = NaiveDate::from_duckdb_value(value)?;
+            let days = (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()).num_days();
+            set(vector, row, days as i32);
+        }
+        VirtualColumnType::Timestamp => {
+            let timestamp = NaiveDateTime::from_duckdb_value(value)?;
+            let micros = timestamp.and_utc().timestamp_micros();
+            set(vector, row, micros);
+        }
+    }
+    Ok(())
+}
+
+fn set<T>(vector: &mut FlatVector, row: usize, value: T) {
+    vector.as_mut_slice::<T>()[row] = value;
+}